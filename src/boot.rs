@@ -0,0 +1,135 @@
+use rp_pico::hal::rom_data;
+
+// the boot counter lives in the last 4 KiB sector of the 2 MiB flash, which
+// memory.x keeps out of the firmware's range. It survives power cycles and
+// brown-outs as well as watchdog resets. A panic or a stuck main loop ends in
+// a watchdog reset, so each one counts
+const XIP_BASE: u32 = 0x1000_0000;
+const SECTOR_SIZE: usize = 4096;
+const SECTOR_OFFSET: u32 = 2048 * 1024 - SECTOR_SIZE as u32;
+const PAGE_SIZE: usize = 256;
+const SECTOR_ERASE: u8 = 0x20;
+
+// each boot programs the next erased word of the sector to this, so the sector
+// is only erased once the unit has run stably
+const BOOT_MARK: u32 = 0xb007_0001;
+const ERASED: u32 = u32::MAX;
+
+const RESET_LIMIT: u32 = 3; // boots in a row without a stable run before giving up
+pub const STABLE_MS: u32 = 10_000; // uptime after which the boot counts as healthy
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticMode {
+    Off,
+    // reset this many times in a row, show the wiring instead of running
+    RepeatedResets(u32),
+}
+
+// counts this boot; call once at start-up, before anything can crash
+pub fn record_boot() -> DiagnosticMode {
+    let words = sector();
+    let mut boots = count_boots(words);
+    if words.get(boots) != Some(&ERASED) {
+        // full, or holding something that isn't a count
+        erase_sector();
+        boots = 0;
+    }
+    program_word(boots, BOOT_MARK);
+    let boots = boots as u32 + 1;
+    if boots >= RESET_LIMIT {
+        DiagnosticMode::RepeatedResets(boots)
+    } else {
+        DiagnosticMode::Off
+    }
+}
+
+// the unit has run for STABLE_MS, so the next boot starts counting afresh
+pub fn mark_stable() {
+    if count_boots(sector()) > 0 {
+        erase_sector();
+    }
+}
+
+// boots recorded so far: the run of marks from the start of the sector
+fn count_boots(words: &[u32]) -> usize {
+    words.iter().take_while(|&&word| word == BOOT_MARK).count()
+}
+
+fn sector() -> &'static [u32] {
+    let start = (XIP_BASE + SECTOR_OFFSET) as *const u32;
+    unsafe { core::slice::from_raw_parts(start, SECTOR_SIZE / 4) }
+}
+
+fn erase_sector() {
+    write_flash(None);
+}
+
+// programming only clears bits, so the rest of the page is written as ones
+// and the marks already there are left alone
+fn program_word(index: usize, value: u32) {
+    let mut page = [0xff; PAGE_SIZE];
+    let offset = index * 4 % PAGE_SIZE;
+    page[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    let address = SECTOR_OFFSET + (index * 4 / PAGE_SIZE * PAGE_SIZE) as u32;
+    write_flash(Some((address, &page)));
+}
+
+// the ROM routines, looked up before flash goes away
+struct Rom {
+    connect_internal_flash: unsafe extern "C" fn(),
+    flash_exit_xip: unsafe extern "C" fn(),
+    flash_range_erase: unsafe extern "C" fn(u32, usize, u32, u8),
+    flash_range_program: unsafe extern "C" fn(u32, *const u8, usize),
+    flash_flush_cache: unsafe extern "C" fn(),
+    // the copy of boot2, which puts XIP back in its fast mode
+    enter_xip: unsafe extern "C" fn(),
+}
+
+// erases the counter sector, or programs one page of it
+fn write_flash(page: Option<(u32, &[u8; PAGE_SIZE])>) {
+    let mut boot2 = [0u32; 256 / 4];
+    unsafe { rom_data::memcpy44(boot2.as_mut_ptr(), XIP_BASE as *const u32, 256) };
+    let rom = Rom {
+        connect_internal_flash: rom_data::connect_internal_flash::ptr(),
+        flash_exit_xip: rom_data::flash_exit_xip::ptr(),
+        flash_range_erase: rom_data::flash_range_erase::ptr(),
+        flash_range_program: rom_data::flash_range_program::ptr(),
+        flash_flush_cache: rom_data::flash_flush_cache::ptr(),
+        // thumb code, so the low bit is set
+        enter_xip: unsafe {
+            core::mem::transmute::<*const u8, unsafe extern "C" fn()>(
+                (boot2.as_ptr() as *const u8).wrapping_add(1),
+            )
+        },
+    };
+    critical_section::with(|_| unsafe { write_flash_in_ram(&rom, page) });
+}
+
+// nothing can be fetched from flash while it's written, so this runs from RAM
+// and only calls into the ROM
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn write_flash_in_ram(rom: &Rom, page: Option<(u32, &[u8; PAGE_SIZE])>) {
+    (rom.connect_internal_flash)();
+    (rom.flash_exit_xip)();
+    match page {
+        Some((address, data)) => (rom.flash_range_program)(address, data.as_ptr(), PAGE_SIZE),
+        None => {
+            (rom.flash_range_erase)(SECTOR_OFFSET, SECTOR_SIZE, SECTOR_SIZE as u32, SECTOR_ERASE)
+        }
+    }
+    (rom.flash_flush_cache)();
+    (rom.enter_xip)();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boots_count_the_marks_up_to_the_first_other_word() {
+        assert_eq!(count_boots(&[ERASED; 4]), 0);
+        assert_eq!(count_boots(&[BOOT_MARK, BOOT_MARK, ERASED, ERASED]), 2);
+        assert_eq!(count_boots(&[BOOT_MARK, 0, BOOT_MARK, ERASED]), 1);
+    }
+}
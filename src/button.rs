@@ -1,15 +1,87 @@
+#[cfg(any(test, feature = "mock-button"))]
+use alloc::rc::Rc;
+use core::cell::Cell;
 use embedded_hal::digital::v2::{InputPin, OutputPin};
+use embedded_hal::PwmPin;
 
-pub trait LedButtonTrait {
-    fn is_pressed(&self) -> Option<bool>;
-    fn turn_on(&mut self) -> Option<()>;
-    fn turn_off(&mut self) -> Option<()>;
-    fn set_on(&mut self, on: bool) -> Option<()>;
+// which pin of a button failed; the HAL's own error types differ per pin,
+// so only the side is kept
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ButtonError {
+    // reading the switch
+    Input,
+    // driving the LED
+    Output,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PressKind {
+    Short,
+    // held for the long-press threshold, reported once while still down
+    Long,
+}
+
+pub trait ButtonTrait {
+    fn is_pressed(&self) -> Result<bool, ButtonError>;
+    // called once per poll; without long-press tracking every read that sees
+    // the button down is a short press
+    fn press_kind(&mut self) -> Result<Option<PressKind>, ButtonError> {
+        Ok(self.is_pressed()?.then_some(PressKind::Short))
+    }
+    // overrides the reported press state, None goes back to the real input;
+    // returns None for buttons that can't be overridden
+    fn set_forced(&mut self, _pressed: Option<bool>) -> Option<()> {
+        None
+    }
+}
+
+pub trait LedTrait {
+    fn set_on(&mut self, on: bool) -> Result<(), ButtonError>;
+    fn turn_on(&mut self) -> Result<(), ButtonError> {
+        self.set_on(true)
+    }
+    fn turn_off(&mut self) -> Result<(), ButtonError> {
+        self.set_on(false)
+    }
+    // dims out over a few polls where the LED can, others just turn off
+    fn fade_off(&mut self) -> Result<(), ButtonError> {
+        self.turn_off()
+    }
+    // called once per poll to move a fade along
+    fn step_fade(&mut self) -> Result<(), ButtonError> {
+        Ok(())
+    }
+}
+
+// a button with an LED behind it, what each floor has
+pub trait LedButtonTrait: ButtonTrait + LedTrait {}
+
+impl<T> LedButtonTrait for T where T: ButtonTrait + LedTrait {}
+
+// the level a pin sits at while a button is pressed or an LED is lit
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Polarity {
+    ActiveLow,
+    ActiveHigh,
 }
 
 pub struct LedButton<LED, BUTTON> {
     led: LED,
     button: BUTTON,
+    led_polarity: Polarity,
+    button_polarity: Polarity,
+    // reads in a row the input must be pressed before it counts, 0 for none
+    debounce: u8,
+    // consecutive pressed reads so far, is_pressed only takes &self
+    pressed_reads: Cell<u8>,
+    // polls held before a press is long, 0 turns long presses off
+    long_press: u16,
+    // polls the current press has been held for
+    held: u16,
+    // (initial delay, period) in polls between repeated presses while held
+    autorepeat: Option<(u16, u16)>,
+    // polls into the current hold, wound back each period
+    repeat_reads: Cell<u16>,
 }
 
 impl<LED, BUTTON> LedButton<LED, BUTTON>
@@ -17,29 +89,682 @@ where
     LED: OutputPin,
     BUTTON: InputPin,
 {
+    // the board's wiring: the button pulls the pin low and the LED sinks
+    // current, so both are active low
     pub fn new(led: LED, button: BUTTON) -> Self {
-        Self { led, button }
+        Self::with_debounce(led, button, 0)
+    }
+
+    // only reports a press once `samples` reads in a row saw it
+    pub fn with_debounce(led: LED, button: BUTTON, samples: u8) -> Self {
+        Self {
+            led,
+            button,
+            led_polarity: Polarity::ActiveLow,
+            button_polarity: Polarity::ActiveLow,
+            debounce: samples,
+            pressed_reads: Cell::new(0),
+            long_press: 0,
+            held: 0,
+            autorepeat: None,
+            repeat_reads: Cell::new(0),
+        }
+    }
+
+    // while held, reports a press on the first poll, again `initial` polls
+    // later and then every `period` polls; releasing starts over
+    pub fn with_autorepeat(mut self, initial: u16, period: u16) -> Self {
+        self.autorepeat = Some((initial, period.max(1)));
+        self
+    }
+
+    // tells presses held for `polls` apart from short ones; short presses
+    // are then reported on release
+    pub fn with_long_press(mut self, polls: u16) -> Self {
+        self.long_press = polls;
+        self
+    }
+
+    // an LED that sources current, lit by driving it high
+    pub fn new_inverted_led(led: LED, button: BUTTON) -> Self {
+        Self::new(led, button).with_polarity(Polarity::ActiveLow, Polarity::ActiveHigh)
+    }
+
+    // a button that pulls the pin high when pressed, with the usual LED
+    pub fn new_active_high(led: LED, button: BUTTON) -> Self {
+        Self::new(led, button).with_polarity(Polarity::ActiveHigh, Polarity::ActiveLow)
+    }
+
+    pub fn with_polarity(mut self, button: Polarity, led: Polarity) -> Self {
+        self.button_polarity = button;
+        self.led_polarity = led;
+        self
+    }
+
+    // turns a steady hold into the autorepeat presses, if configured
+    fn repeat(&self, pressed: bool) -> Result<bool, ButtonError> {
+        let Some((initial, period)) = self.autorepeat else {
+            return Ok(pressed);
+        };
+        if !pressed {
+            self.repeat_reads.set(0);
+            return Ok(false);
+        }
+        let reads = self.repeat_reads.get();
+        let fire = reads == 0 || (reads >= initial && (reads - initial).is_multiple_of(period));
+        // wind back by a period so a long hold can't overflow the count
+        let next = reads as u32 + 1;
+        let next = if next >= initial as u32 + period as u32 {
+            initial
+        } else {
+            next as u16
+        };
+        self.repeat_reads.set(next);
+        Ok(fire)
+    }
+}
+
+impl<LED, BUTTON> ButtonTrait for LedButton<LED, BUTTON>
+where
+    LED: OutputPin,
+    BUTTON: InputPin,
+{
+    fn is_pressed(&self) -> Result<bool, ButtonError> {
+        let pressed = match self.button_polarity {
+            Polarity::ActiveLow => self.button.is_low(),
+            Polarity::ActiveHigh => self.button.is_high(),
+        }
+        .map_err(|_| ButtonError::Input)?;
+        if self.debounce == 0 {
+            return self.repeat(pressed);
+        }
+        let reads = if pressed {
+            self.pressed_reads
+                .get()
+                .saturating_add(1)
+                .min(self.debounce)
+        } else {
+            0
+        };
+        self.pressed_reads.set(reads);
+        self.repeat(reads >= self.debounce)
+    }
+
+    fn press_kind(&mut self) -> Result<Option<PressKind>, ButtonError> {
+        let pressed = self.is_pressed()?;
+        if self.long_press == 0 {
+            return Ok(pressed.then_some(PressKind::Short));
+        }
+        if pressed {
+            self.held = self.held.saturating_add(1);
+            return Ok((self.held == self.long_press).then_some(PressKind::Long));
+        }
+        // released; a long press was already reported while held
+        let held = core::mem::take(&mut self.held);
+        Ok((held > 0 && held < self.long_press).then_some(PressKind::Short))
     }
 }
 
-impl<LED, BUTTON> LedButtonTrait for LedButton<LED, BUTTON>
+impl<LED, BUTTON> LedTrait for LedButton<LED, BUTTON>
 where
     LED: OutputPin,
     BUTTON: InputPin,
 {
-    fn is_pressed(&self) -> Option<bool> {
-        self.button.is_low().ok()
+    fn set_on(&mut self, on: bool) -> Result<(), ButtonError> {
+        let high = on == (self.led_polarity == Polarity::ActiveHigh);
+        self.led
+            .set_state(high.into())
+            .map_err(|_| ButtonError::Output)
+    }
+}
+
+// wraps a button so its press state can be scripted, e.g. from the serial protocol
+pub struct ForcedButton<B> {
+    inner: B,
+    forced: Option<bool>,
+}
+
+impl<B> ForcedButton<B>
+where
+    B: ButtonTrait,
+{
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            forced: None,
+        }
+    }
+}
+
+impl<B> ButtonTrait for ForcedButton<B>
+where
+    B: ButtonTrait,
+{
+    fn is_pressed(&self) -> Result<bool, ButtonError> {
+        match self.forced {
+            Some(pressed) => Ok(pressed),
+            None => self.inner.is_pressed(),
+        }
+    }
+
+    fn press_kind(&mut self) -> Result<Option<PressKind>, ButtonError> {
+        match self.forced {
+            Some(pressed) => Ok(pressed.then_some(PressKind::Short)),
+            None => self.inner.press_kind(),
+        }
+    }
+
+    fn set_forced(&mut self, pressed: Option<bool>) -> Option<()> {
+        self.forced = pressed;
+        Some(())
+    }
+}
+
+impl<B> LedTrait for ForcedButton<B>
+where
+    B: LedTrait,
+{
+    fn set_on(&mut self, on: bool) -> Result<(), ButtonError> {
+        self.inner.set_on(on)
+    }
+
+    fn fade_off(&mut self) -> Result<(), ButtonError> {
+        self.inner.fade_off()
+    }
+
+    fn step_fade(&mut self) -> Result<(), ButtonError> {
+        self.inner.step_fade()
+    }
+}
+
+// an LED on a PWM channel, which can fade out instead of snapping off
+pub struct PwmLed<P> {
+    pin: P,
+    // polls a fade-out takes, 0 turns the LED straight off
+    fade: u16,
+    // (duty the fade started from, polls left)
+    fading: Option<(u16, u16)>,
+}
+
+impl<P> PwmLed<P>
+where
+    P: PwmPin<Duty = u16>,
+{
+    pub fn new(mut pin: P) -> Self {
+        pin.set_duty(0);
+        pin.enable();
+        Self {
+            pin,
+            fade: 0,
+            fading: None,
+        }
+    }
+
+    pub fn with_fade(mut self, polls: u16) -> Self {
+        self.fade = polls;
+        self
+    }
+}
+
+impl<P> LedTrait for PwmLed<P>
+where
+    P: PwmPin<Duty = u16>,
+{
+    fn set_on(&mut self, on: bool) -> Result<(), ButtonError> {
+        self.fading = None;
+        let duty = if on { self.pin.get_max_duty() } else { 0 };
+        self.pin.set_duty(duty);
+        Ok(())
+    }
+
+    fn fade_off(&mut self) -> Result<(), ButtonError> {
+        let duty = self.pin.get_duty();
+        if self.fade == 0 || duty == 0 {
+            return self.turn_off();
+        }
+        self.fading = Some((duty, self.fade));
+        Ok(())
+    }
+
+    fn step_fade(&mut self) -> Result<(), ButtonError> {
+        if let Some((start, left)) = self.fading {
+            let left = left - 1;
+            self.pin
+                .set_duty((start as u32 * left as u32 / self.fade as u32) as u16);
+            self.fading = (left > 0).then_some((start, left));
+        }
+        Ok(())
+    }
+}
+
+// a button and a separate LED working as one, e.g. a PlainButton with a PwmLed
+pub struct WithLed<B, L> {
+    button: B,
+    led: L,
+}
+
+impl<B, L> WithLed<B, L>
+where
+    B: ButtonTrait,
+    L: LedTrait,
+{
+    pub fn new(button: B, led: L) -> Self {
+        Self { button, led }
+    }
+}
+
+impl<B, L> ButtonTrait for WithLed<B, L>
+where
+    B: ButtonTrait,
+{
+    fn is_pressed(&self) -> Result<bool, ButtonError> {
+        self.button.is_pressed()
+    }
+
+    fn press_kind(&mut self) -> Result<Option<PressKind>, ButtonError> {
+        self.button.press_kind()
+    }
+
+    fn set_forced(&mut self, pressed: Option<bool>) -> Option<()> {
+        self.button.set_forced(pressed)
+    }
+}
+
+impl<B, L> LedTrait for WithLed<B, L>
+where
+    L: LedTrait,
+{
+    fn set_on(&mut self, on: bool) -> Result<(), ButtonError> {
+        self.led.set_on(on)
+    }
+
+    fn fade_off(&mut self) -> Result<(), ButtonError> {
+        self.led.fade_off()
+    }
+
+    fn step_fade(&mut self) -> Result<(), ButtonError> {
+        self.led.step_fade()
+    }
+}
+
+// a bare momentary switch, with no LED to drive
+pub struct PlainButton<BUTTON> {
+    button: BUTTON,
+    polarity: Polarity,
+}
+
+impl<BUTTON> PlainButton<BUTTON>
+where
+    BUTTON: InputPin,
+{
+    // a switch to ground, pressed when low
+    pub fn new(button: BUTTON) -> Self {
+        Self {
+            button,
+            polarity: Polarity::ActiveLow,
+        }
+    }
+
+    pub fn new_active_high(button: BUTTON) -> Self {
+        Self::new(button).with_polarity(Polarity::ActiveHigh)
+    }
+
+    pub fn with_polarity(mut self, polarity: Polarity) -> Self {
+        self.polarity = polarity;
+        self
+    }
+}
+
+impl<BUTTON> ButtonTrait for PlainButton<BUTTON>
+where
+    BUTTON: InputPin,
+{
+    fn is_pressed(&self) -> Result<bool, ButtonError> {
+        match self.polarity {
+            Polarity::ActiveLow => self.button.is_low(),
+            Polarity::ActiveHigh => self.button.is_high(),
+        }
+        .map_err(|_| ButtonError::Input)
+    }
+}
+
+// lets a button without an LED stand in where one is expected; lighting it
+// does nothing
+pub struct Unlit<B> {
+    inner: B,
+}
+
+impl<B> Unlit<B>
+where
+    B: ButtonTrait,
+{
+    pub fn new(inner: B) -> Self {
+        Self { inner }
+    }
+}
+
+impl<B> ButtonTrait for Unlit<B>
+where
+    B: ButtonTrait,
+{
+    fn is_pressed(&self) -> Result<bool, ButtonError> {
+        self.inner.is_pressed()
+    }
+
+    fn press_kind(&mut self) -> Result<Option<PressKind>, ButtonError> {
+        self.inner.press_kind()
+    }
+
+    fn set_forced(&mut self, pressed: Option<bool>) -> Option<()> {
+        self.inner.set_forced(pressed)
+    }
+}
+
+impl<B> LedTrait for Unlit<B> {
+    fn set_on(&mut self, _on: bool) -> Result<(), ButtonError> {
+        Ok(())
+    }
+}
+
+// a read-only input such as a key switch or sensor
+pub trait InputTrait {
+    fn is_active(&self) -> Result<bool, ButtonError>;
+}
+
+// an input pin with no LED beside it
+pub struct InputOnly<B> {
+    input: B,
+    // active when driven high instead of low
+    active_high: bool,
+}
+
+impl<B> InputOnly<B>
+where
+    B: InputPin,
+{
+    pub fn new(input: B) -> Self {
+        Self {
+            input,
+            active_high: false,
+        }
+    }
+
+    pub fn new_active_high(input: B) -> Self {
+        Self {
+            input,
+            active_high: true,
+        }
+    }
+}
+
+impl<B> InputTrait for InputOnly<B>
+where
+    B: InputPin,
+{
+    fn is_active(&self) -> Result<bool, ButtonError> {
+        let high = self.input.is_high().map_err(|_| ButtonError::Input)?;
+        Ok(high == self.active_high)
+    }
+}
+
+// a button with no pins: the press is set from code and the LED only
+// remembers its state; clones share both, so keep one to drive the one
+// handed to the elevator
+#[cfg(any(test, feature = "mock-button"))]
+#[derive(Clone, Default)]
+pub struct MockButton {
+    pressed: Rc<Cell<bool>>,
+    lit: Rc<Cell<bool>>,
+}
+
+#[cfg(any(test, feature = "mock-button"))]
+impl MockButton {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_pressed(&self, pressed: bool) {
+        self.pressed.set(pressed);
     }
 
-    fn turn_on(&mut self) -> Option<()> {
-        self.led.set_low().ok()
+    pub fn led_is_on(&self) -> bool {
+        self.lit.get()
+    }
+}
+
+#[cfg(any(test, feature = "mock-button"))]
+impl ButtonTrait for MockButton {
+    fn is_pressed(&self) -> Result<bool, ButtonError> {
+        Ok(self.pressed.get())
+    }
+}
+
+#[cfg(any(test, feature = "mock-button"))]
+impl LedTrait for MockButton {
+    fn set_on(&mut self, on: bool) -> Result<(), ButtonError> {
+        self.lit.set(on);
+        Ok(())
+    }
+}
+
+// a pin whose level is shared between clones, so tests can drive a real
+// LedButton: set it low to press an active-low button, read it back for the LED
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct MockPin {
+    high: Rc<Cell<bool>>,
+}
+
+#[cfg(test)]
+impl MockPin {
+    pub fn high() -> Self {
+        let pin = Self::default();
+        pin.set(true);
+        pin
+    }
+
+    pub fn set(&self, high: bool) {
+        self.high.set(high);
+    }
+
+    pub fn get(&self) -> bool {
+        self.high.get()
+    }
+}
+
+#[cfg(test)]
+impl InputPin for MockPin {
+    type Error = core::convert::Infallible;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.high.get())
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(!self.high.get())
+    }
+}
+
+#[cfg(test)]
+impl OutputPin for MockPin {
+    type Error = core::convert::Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.high.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.high.set(true);
+        Ok(())
+    }
+}
+
+// a PWM channel that only remembers its duty, shared between clones
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct MockPwm {
+    duty: Rc<Cell<u16>>,
+}
+
+#[cfg(test)]
+impl MockPwm {
+    pub const MAX_DUTY: u16 = 1000;
+
+    pub fn duty(&self) -> u16 {
+        self.duty.get()
+    }
+}
+
+#[cfg(test)]
+impl PwmPin for MockPwm {
+    type Duty = u16;
+
+    fn disable(&mut self) {}
+
+    fn enable(&mut self) {}
+
+    fn get_duty(&self) -> u16 {
+        self.duty.get()
+    }
+
+    fn get_max_duty(&self) -> u16 {
+        Self::MAX_DUTY
+    }
+
+    fn set_duty(&mut self, duty: u16) {
+        self.duty.set(duty);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_pwm_led_fades_down_to_off() {
+        let pwm = MockPwm::default();
+        let mut led = PwmLed::new(pwm.clone()).with_fade(8);
+        led.turn_on().unwrap();
+        assert_eq!(pwm.duty(), MockPwm::MAX_DUTY);
+        led.fade_off().unwrap();
+        let mut duties = Vec::new();
+        for _ in 0..10 {
+            led.step_fade().unwrap();
+            duties.push(pwm.duty());
+        }
+        assert!(duties.windows(2).all(|w| w[1] < w[0] || w[1] == 0));
+        assert_eq!(duties[7..], [0, 0, 0]);
+        assert!(duties[6] > 0);
+        // relit mid-fade, it stays lit
+        led.turn_on().unwrap();
+        led.fade_off().unwrap();
+        led.step_fade().unwrap();
+        led.turn_on().unwrap();
+        led.step_fade().unwrap();
+        assert_eq!(pwm.duty(), MockPwm::MAX_DUTY);
+    }
+
+    #[test]
+    fn a_plain_button_follows_its_polarity() {
+        let input = MockPin::high();
+        let low = PlainButton::new(input.clone());
+        let high = PlainButton::new_active_high(input.clone());
+        assert_eq!((low.is_pressed(), high.is_pressed()), (Ok(false), Ok(true)));
+        input.set(false);
+        assert_eq!((low.is_pressed(), high.is_pressed()), (Ok(true), Ok(false)));
+    }
+
+    #[test]
+    fn autorepeat_combines_with_debounce() {
+        let input = MockPin::high();
+        let button =
+            LedButton::with_debounce(MockPin::high(), input.clone(), 2).with_autorepeat(3, 2);
+        input.set(false);
+        let presses: Vec<bool> = (0..9).map(|_| button.is_pressed().unwrap()).collect();
+        // debounced on the second read, then again 3 reads on and every 2 after
+        assert_eq!(
+            presses,
+            [false, true, false, false, true, false, true, false, true]
+        );
+        input.set(true);
+        assert_eq!(button.is_pressed(), Ok(false));
+        input.set(false);
+        assert_eq!(button.is_pressed(), Ok(false));
+        assert_eq!(button.is_pressed(), Ok(true));
+    }
+
+    #[test]
+    fn an_input_only_pin_follows_its_polarity() {
+        let input = MockPin::high();
+        let low = InputOnly::new(input.clone());
+        let high = InputOnly::new_active_high(input.clone());
+        assert_eq!((low.is_active(), high.is_active()), (Ok(false), Ok(true)));
+        input.set(false);
+        assert_eq!((low.is_active(), high.is_active()), (Ok(true), Ok(false)));
+    }
+
+    #[test]
+    fn an_inverted_led_button_debounces_and_lights_high() {
+        let (led, input) = (MockPin::default(), MockPin::high());
+        let mut button = LedButton::with_debounce(led.clone(), input.clone(), 2)
+            .with_long_press(15)
+            .with_polarity(Polarity::ActiveLow, Polarity::ActiveHigh);
+        button.turn_on().unwrap();
+        assert!(led.get());
+        button.turn_off().unwrap();
+        assert!(!led.get());
+
+        // one pressed read is a glitch, releasing after two is a short press
+        input.set(false);
+        assert_eq!(button.press_kind(), Ok(None));
+        input.set(true);
+        assert_eq!(button.press_kind(), Ok(None));
+        input.set(false);
+        assert_eq!(button.press_kind(), Ok(None));
+        assert_eq!(button.press_kind(), Ok(None));
+        input.set(true);
+        assert_eq!(button.press_kind(), Ok(Some(PressKind::Short)));
     }
 
-    fn turn_off(&mut self) -> Option<()> {
-        self.led.set_high().ok()
+    #[test]
+    fn debounce_ignores_glitches_shorter_than_the_sample_count() {
+        let input = MockPin::high();
+        let debounced = LedButton::with_debounce(MockPin::high(), input.clone(), 3);
+        let plain = LedButton::new(MockPin::high(), input.clone());
+        // bounces of one and two reads, then a steady press
+        let mut reads = Vec::new();
+        for level in [false, true, false, false, true, false, false, false, false] {
+            input.set(level);
+            reads.push((plain.is_pressed().unwrap(), debounced.is_pressed().unwrap()));
+        }
+        let (plain, debounced): (Vec<bool>, Vec<bool>) = reads.into_iter().unzip();
+        assert_eq!(
+            plain,
+            [true, false, true, true, false, true, true, true, true]
+        );
+        assert_eq!(
+            debounced,
+            [false, false, false, false, false, false, false, true, true]
+        );
     }
 
-    fn set_on(&mut self, on: bool) -> Option<()> {
-        self.led.set_state(on.into()).ok()
+    #[test]
+    fn a_held_button_repeats_on_a_tick_cadence_and_release_resets_it() {
+        let input = MockPin::high();
+        let button = LedButton::new(MockPin::high(), input.clone()).with_autorepeat(4, 3);
+        input.set(false);
+        let presses: Vec<bool> = (0..11).map(|_| button.is_pressed().unwrap()).collect();
+        // the first poll, 4 polls later, then every 3
+        assert_eq!(
+            presses,
+            [true, false, false, false, true, false, false, true, false, false, true]
+        );
+        // a release in between starts the delay over
+        input.set(true);
+        assert_eq!(button.is_pressed(), Ok(false));
+        input.set(false);
+        let presses: Vec<bool> = (0..5).map(|_| button.is_pressed().unwrap()).collect();
+        assert_eq!(presses, [true, false, false, false, true]);
     }
 }
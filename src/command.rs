@@ -0,0 +1,286 @@
+use crate::elevator::Elevator;
+use crate::group::ElevatorGroup;
+use core::fmt;
+
+const SAY_LENGTH: usize = 64; // longest phrase accepted by `say`
+const LINE_LENGTH: usize = SAY_LENGTH + 4; // room for "say " and the phrase
+
+pub enum Command {
+    // speak an arbitrary ATP3012 phrase, for tuning pronunciation
+    Say(heapless::Vec<u8, SAY_LENGTH>),
+    // `quiet on` or `quiet off`
+    Quiet(bool),
+    // `recall` and `release all`, for every car when applied to a group
+    RecallAll,
+    ReleaseAll,
+    // `press <floor>` holds a floor button down, `release <floor>` hands it back
+    // to the real input
+    Press(i8),
+    Release(i8),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParseError {
+    UnknownVerb,
+    // `say`, `press`, `release` or `quiet` with nothing after it
+    MissingArg,
+    // `quiet` with something other than on or off, or a floor that isn't a number
+    BadArg,
+    // a number, but not a floor this building has
+    FloorOutOfRange,
+    // a `say` phrase longer than SAY_LENGTH, or a line longer than LINE_LENGTH
+    TooLong,
+}
+
+// one line describing the error
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnknownVerb => f.write_str("error: unknown command"),
+            ParseError::MissingArg => f.write_str("error: missing argument"),
+            ParseError::BadArg => f.write_str("error: bad argument"),
+            ParseError::FloorOutOfRange => f.write_str("error: floor out of range"),
+            ParseError::TooLong => write!(f, "error: longer than {} bytes", SAY_LENGTH),
+        }
+    }
+}
+
+// parses one line of the serial protocol, with or without its line ending
+pub fn parse_command(line: &[u8]) -> Result<Command, ParseError> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let (verb, arg) = match line.iter().position(|&b| b == b' ') {
+        Some(space) => (&line[..space], Some(&line[space + 1..])),
+        None => (line, None),
+    };
+    let arg = arg.filter(|arg| !arg.is_empty());
+    match (verb, arg) {
+        (b"say", Some(phrase)) => heapless::Vec::from_slice(phrase)
+            .map(Command::Say)
+            .map_err(|_| ParseError::TooLong),
+        (b"press", Some(floor)) => parse_floor(floor).map(Command::Press),
+        (b"release", Some(b"all")) => Ok(Command::ReleaseAll),
+        (b"release", Some(floor)) => parse_floor(floor).map(Command::Release),
+        (b"quiet", Some(b"on")) => Ok(Command::Quiet(true)),
+        (b"quiet", Some(b"off")) => Ok(Command::Quiet(false)),
+        (b"quiet", Some(_)) => Err(ParseError::BadArg),
+        (b"recall", None) => Ok(Command::RecallAll),
+        (b"say" | b"press" | b"release" | b"quiet", None) => Err(ParseError::MissingArg),
+        _ => Err(ParseError::UnknownVerb),
+    }
+}
+
+fn parse_floor(text: &[u8]) -> Result<i8, ParseError> {
+    let text = core::str::from_utf8(text)
+        .map_err(|_| ParseError::BadArg)?
+        .trim();
+    let number: i32 = text.parse().map_err(|_| ParseError::BadArg)?;
+    i8::try_from(number).map_err(|_| ParseError::FloorOutOfRange)
+}
+
+// collects serial bytes into command lines. The commands share UART0 with
+// the ATP3012, so its own replies, a `>` prompt or an `E` code, are skipped
+pub struct LineReader {
+    line: heapless::Vec<u8, LINE_LENGTH>,
+    overflowed: bool,
+}
+
+impl Default for LineReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineReader {
+    pub fn new() -> Self {
+        Self {
+            line: heapless::Vec::new(),
+            overflowed: false,
+        }
+    }
+
+    // feeds one byte, giving back the parsed command once a line ends
+    pub fn push(&mut self, byte: u8) -> Option<Result<Command, ParseError>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let overflowed = core::mem::take(&mut self.overflowed);
+                let line = core::mem::take(&mut self.line);
+                match line.first() {
+                    _ if overflowed => Some(Err(ParseError::TooLong)),
+                    None | Some(b'E') => None,
+                    Some(_) => Some(parse_command(&line)),
+                }
+            }
+            b'>' if self.line.is_empty() => None,
+            byte => {
+                self.overflowed |= self.line.push(byte).is_err();
+                None
+            }
+        }
+    }
+}
+
+impl Command {
+    pub fn apply(&self, elevator: &mut Elevator) -> Result<(), ParseError> {
+        match self {
+            Command::Say(phrase) => elevator.say(phrase),
+            Command::Quiet(on) => elevator.set_quiet(*on),
+            Command::RecallAll => elevator.recall(),
+            Command::ReleaseAll => elevator.release_recall(),
+            Command::Press(floor) | Command::Release(floor) => {
+                if elevator.try_floor_to_index(*floor).is_none() {
+                    return Err(ParseError::FloorOutOfRange);
+                }
+                let pressed = matches!(self, Command::Press(_)).then_some(true);
+                elevator.force_button(*floor, pressed);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply_to_group(&self, group: &mut ElevatorGroup) -> Result<(), ParseError> {
+        match self {
+            Command::RecallAll => group.recall_all(),
+            Command::ReleaseAll => group.release_all(),
+            _ => {
+                for index in 0..group.cars().len() {
+                    self.apply(group.car_mut(index))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevator::fixture::elevator;
+
+    fn error(line: &[u8]) -> ParseError {
+        parse_command(line).err().expect("parsed")
+    }
+
+    #[test]
+    fn parses_each_verb() {
+        assert!(
+            matches!(parse_command(b"say konnichiwa.\r\n"), Ok(Command::Say(p)) if p == b"konnichiwa.")
+        );
+        assert!(matches!(
+            parse_command(b"quiet on"),
+            Ok(Command::Quiet(true))
+        ));
+        assert!(matches!(
+            parse_command(b"quiet off"),
+            Ok(Command::Quiet(false))
+        ));
+        assert!(matches!(parse_command(b"recall"), Ok(Command::RecallAll)));
+        assert!(matches!(
+            parse_command(b"release all"),
+            Ok(Command::ReleaseAll)
+        ));
+        assert!(matches!(parse_command(b"press -2"), Ok(Command::Press(-2))));
+        assert!(matches!(
+            parse_command(b"release 3\n"),
+            Ok(Command::Release(3))
+        ));
+    }
+
+    #[test]
+    fn reports_each_error() {
+        assert_eq!(error(b"open"), ParseError::UnknownVerb);
+        assert_eq!(error(b"press"), ParseError::MissingArg);
+        assert_eq!(error(b"say "), ParseError::MissingArg);
+        assert_eq!(error(b"release"), ParseError::MissingArg);
+        assert_eq!(error(b"release "), ParseError::MissingArg);
+        assert_eq!(error(b"quiet loud"), ParseError::BadArg);
+        assert_eq!(error(b"press three"), ParseError::BadArg);
+        assert_eq!(error(b"press 300"), ParseError::FloorOutOfRange);
+        assert_eq!(
+            error(&[b"say ".as_slice(), &[b'a'; 65]].concat()),
+            ParseError::TooLong
+        );
+    }
+
+    #[test]
+    fn a_floor_the_building_lacks_is_out_of_range() {
+        let (mut elevator, _buttons) = elevator();
+        assert_eq!(
+            Command::Press(7).apply(&mut elevator),
+            Err(ParseError::FloorOutOfRange)
+        );
+        assert_eq!(
+            Command::Release(0).apply(&mut elevator),
+            Err(ParseError::FloorOutOfRange)
+        );
+        assert_eq!(Command::Press(6).apply(&mut elevator), Ok(()));
+    }
+
+    #[test]
+    fn say_hands_the_phrase_to_the_announcer() {
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+        let (mut elevator, _buttons) = elevator();
+        let spoken = Rc::new(RefCell::new(Vec::new()));
+        let sink = spoken.clone();
+        elevator.on_announce(move |message: &[u8]| sink.borrow_mut().push(message.to_vec()));
+
+        let mut reader = LineReader::new();
+        for &byte in b"say ko'nnichiwa.\r\n" {
+            if let Some(command) = reader.push(byte) {
+                command.unwrap().apply(&mut elevator).unwrap();
+            }
+        }
+        // spoken from the output phase, like every other announcement
+        assert!(spoken.borrow().is_empty());
+        elevator.dispatch();
+        assert_eq!(*spoken.borrow(), [b"ko'nnichiwa.\r".to_vec()]);
+    }
+
+    #[test]
+    fn a_rejected_command_goes_into_the_event_log() {
+        use crate::elevator::ElevatorEvent;
+        let (mut elevator, _buttons) = elevator();
+        let mut reader = LineReader::new();
+        for &byte in b"press 9\r\n" {
+            if let Some(command) = reader.push(byte) {
+                if let Err(error) = command.and_then(|command| command.apply(&mut elevator)) {
+                    elevator.report_command_error(error);
+                }
+            }
+        }
+        assert!(elevator
+            .recent_events()
+            .eq(&[ElevatorEvent::CommandRejected(ParseError::FloorOutOfRange)]));
+    }
+
+    #[test]
+    fn errors_read_as_one_line() {
+        let line = format!("{}", ParseError::TooLong);
+        assert_eq!(line, "error: longer than 64 bytes");
+        assert!(!line.contains('\n'));
+    }
+
+    #[test]
+    fn line_reader_skips_speech_chip_replies() {
+        let mut reader = LineReader::new();
+        let mut commands = b">E255\r>recall\r\n"
+            .iter()
+            .filter_map(|&byte| reader.push(byte));
+        assert!(matches!(commands.next(), Some(Ok(Command::RecallAll))));
+        assert!(commands.next().is_none());
+    }
+
+    #[test]
+    fn line_reader_reports_an_overlong_line() {
+        let mut reader = LineReader::new();
+        for _ in 0..LINE_LENGTH + 1 {
+            assert!(reader.push(b'a').is_none());
+        }
+        assert_eq!(
+            reader.push(b'\n').map(|r| r.err()),
+            Some(Some(ParseError::TooLong))
+        );
+        assert!(reader.push(b'\n').is_none());
+    }
+}
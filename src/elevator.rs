@@ -1,23 +1,91 @@
-use crate::button::LedButtonTrait;
-use alloc::{boxed::Box, format};
+use crate::button::{ButtonError, ButtonTrait, InputTrait, LedButtonTrait, PressKind};
+use crate::command::ParseError;
+use crate::sound::{Sound, SoundEffect};
+use crate::util::XorShift32;
+use alloc::{boxed::Box, format, rc::Rc, vec::Vec};
+use core::{cell::Cell, fmt, mem::discriminant};
 use embedded_graphics::{
     draw_target::DrawTarget,
     geometry::{Point, Size},
-    mono_font::{ascii::FONT_10X20, ascii::FONT_5X8, MonoTextStyleBuilder},
+    mono_font::{ascii::FONT_5X8, MonoTextStyleBuilder},
     pixelcolor::BinaryColor,
-    primitives::{PrimitiveStyleBuilder, Rectangle, StyledDrawable, Triangle},
-    text::Text,
+    primitives::{
+        Line, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle, StyledDrawable, Triangle,
+    },
+    text::{Alignment, Text},
+    Drawable, Pixel,
 };
 use rp_pico::pac::pio0::flevel;
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg(feature = "big-font")]
+use embedded_graphics::mono_font::ascii::FONT_10X20;
+
+pub const TICK_MS: u32 = 100; // advance is expected to be called at this interval
+
+pub const EVENT_LOG_SIZE: usize = 16; // events kept for post-mortem debugging
+
+const DOOR_MOVE_STEP: u8 = 5; // progress per tick while opening or closing
+const DOOR_OPEN_STEP: u8 = 2; // progress per tick while held open
+const DEPARTURE_GRACE: u8 = 10; // travel progress below which the open button still reopens
+
+const DEFAULT_TRAVEL_MS: u32 = 5000; // per floor
+
+const RAMP_PROGRESS: u8 = 30; // travel progress spent speeding up from, or slowing for, a stop
+const RAMP_MIN_SPEED: u8 = 25; // percent of full speed at a standstill with the ramp on
+
+const TONE_MIN_HZ: u32 = 220; // travelling tone at a standstill
+const TONE_MAX_HZ: u32 = 880; // and at full speed
+const TONE_UPDATE_TICKS: u32 = 3; // shortest gap between changes of tone while travelling
+
+const SELF_TEST_STEP: u32 = 3; // ticks each floor LED stays lit during the self-test
+
+const PARK_DELAY: u32 = 100; // 10 secs of idle before parking
+pub const PARKING_WINDOWS: usize = 4;
+
+const DIM_DELAY: u32 = 600; // 60 secs of idle before dimming the display
+const DIM_CONTRAST: u8 = 0x10;
+const BRIGHT_CONTRAST: u8 = 0xff;
+
+const ATTRACT_DELAY: u32 = 300; // 30 secs of idle before the LED chase starts
+const ATTRACT_STEP: u32 = 2; // ticks per floor while chasing
+
+const PRIORITY_BLINK: u32 = 5; // ticks per on or off phase of a priority call LED
+const REJECT_BLINKS: u32 = 3; // flashes of a refused call to an out-of-service floor
+const PASS_BLINKS: u32 = 2; // flashes of a called floor the car passes without stopping
+
+const DEMO_RESUME_DELAY: u32 = 600; // 60 secs of idle after a real call before demo calls resume
+
+// progress increment per tick for a phase lasting `ms`
+fn travel_step(ms: u32) -> u8 {
+    (100 * TICK_MS / ms.max(1)).clamp(1, 100) as u8
+}
+
+// time left until progress reaches 100 at `step` per tick
+fn remaining_ms(progress: u8, step: u8) -> u32 {
+    (100 - progress.min(100)).div_ceil(step) as u32 * TICK_MS
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Direction {
     Up(Option<u8>),
     Down(Option<u8>),
     Idle,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+// short labels for overlays and logs; the OLED fonts are ASCII only
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Direction::Up(Some(progress)) => write!(f, "Up {}%", progress),
+            Direction::Up(None) => f.write_str("Up"),
+            Direction::Down(Some(progress)) => write!(f, "Down {}%", progress),
+            Direction::Down(None) => f.write_str("Down"),
+            Direction::Idle => f.write_str("Idle"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum DoorState {
     Opening(u8),
     Open(u8),
@@ -25,11 +93,347 @@ pub enum DoorState {
     Closed,
 }
 
+impl fmt::Display for DoorState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DoorState::Opening(progress) => write!(f, "Opening {}%", progress),
+            DoorState::Open(_) => f.write_str("Open"),
+            DoorState::Closing(progress) => write!(f, "Closing {}%", progress),
+            DoorState::Closed => f.write_str("Closed"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ElevatorEvent {
+    // stopped at a floor to serve it
+    Arrived(i8),
+    // started travelling from a standstill
+    Departed(Direction),
+    DoorOpened(i8),
+    DoorClosed(i8),
+    DirectionChanged(Direction),
+    // a level sensor moved the car to this floor
+    LevelCorrected(i8),
+    // restore was handed an impossible state and clamped it
+    StateClamped,
+    // told to travel past the end of the shaft at this floor, stopped instead
+    ShaftEnd(i8),
+    // a button pin failed, with the floor it belongs to if any
+    PinFault(Option<i8>, ButtonError),
+    // the load sensor tripped, the doors are held open until it clears
+    Overload,
+    // travel was halted mid-trip by pause_travel
+    Emergency,
+    // a serial command that couldn't be parsed or carried out
+    CommandRejected(ParseError),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ServiceState {
+    Normal,
+    // sent to the lobby with the doors held open, calls are ignored; a
+    // recall for lost comms ends with the next feed_comm, others on release
+    Recall { comm_loss: bool },
+    // moved by an operator holding up or down, calls are ignored
+    Manual,
+    // finishing the door cycle and parking at the lobby before switching off
+    ShuttingDown,
+    // shut down, inputs are ignored until reset
+    Off,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DoorStyle {
+    // two leaves parting in the middle
+    CenterOpening,
+    // one leaf sliding away into the left wall
+    SideOpeningLeft,
+    SideOpeningRight,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DisplayMode {
+    // doors, floor list and arrows
+    Normal,
+    // a shaft with every floor and the car moving through it
+    Schematic,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TrafficMode {
+    Normal,
+    // idle cars park at the lobby
+    UpPeak,
+    // idle cars serve calls below before calls above
+    DownPeak,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Scheduler {
+    // keep going while there are calls ahead, then reverse
+    Look,
+    // at each decision, head the way that keeps the age-weighted wait lowest
+    Cost,
+}
+
+// keeps the doors from closing until dropped, see Elevator::acquire_hold
+pub struct HoldToken(Rc<Cell<u32>>);
+
+impl Drop for HoldToken {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
+    }
+}
+
+// the externally visible state, for callers that poll instead of using events
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Snapshot {
+    floor: i8,
+    direction: Direction,
+    door: DoorState,
+    calls: u8, // bit per floor index
+}
+
+// which parts of a Snapshot differ
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChangeSet {
+    pub floor: bool,
+    pub direction: bool,
+    pub door: bool,
+    pub calls: bool,
+}
+
+impl Snapshot {
+    // `calls` has a bit per floor index, lowest floor first
+    pub fn new(floor: i8, direction: Direction, door: DoorState, calls: u8) -> Self {
+        Self {
+            floor,
+            direction,
+            door,
+            calls,
+        }
+    }
+}
+
+impl ChangeSet {
+    pub fn is_empty(&self) -> bool {
+        !(self.floor || self.direction || self.door || self.calls)
+    }
+}
+
+// blinks a floor LED when its call latches, before it goes solid
+#[derive(Clone, Copy)]
+pub struct AckPattern {
+    pub blinks: u32,
+    pub step_ticks: u32, // ticks the LED spends on, then off, per blink
+}
+
+impl Default for AckPattern {
+    fn default() -> Self {
+        Self {
+            blinks: 3,
+            step_ticks: 1,
+        }
+    }
+}
+
+// random calls to keep an unattended car moving
+pub struct DemoTraffic {
+    rng: XorShift32,
+    interval: u32,
+    countdown: u32,
+    paused: bool,
+}
+
+impl DemoTraffic {
+    // calls a random floor every `interval_ms`
+    pub fn new(seed: u32, interval_ms: u32) -> Self {
+        let interval = (interval_ms / TICK_MS).max(1);
+        Self {
+            rng: XorShift32::new(seed),
+            interval,
+            countdown: interval,
+            paused: false,
+        }
+    }
+}
+
+/// Framing wrapped around every phrase handed to `on_announce`. The
+/// phrases themselves carry no device-specific terminator.
+#[derive(Clone, Copy)]
+pub struct AnnounceFormat {
+    pub prefix: &'static [u8],
+    pub terminator: &'static [u8],
+}
+
+impl Default for AnnounceFormat {
+    // ATP3012 expects each phrase to end with a carriage return
+    fn default() -> Self {
+        Self {
+            prefix: b"",
+            terminator: b"\r",
+        }
+    }
+}
+
+// the language announcements are spoken in; the ATP3012 only reads romaji,
+// so the English phrases are spelled the way it should pronounce them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Language {
+    Japanese,
+    English,
+}
+
+// the fixed phrases of one language, floor names are built by Language::floor
+struct Phrases {
+    up: &'static [u8],
+    down: &'static [u8],
+    closing: &'static [u8],
+    stand_clear: &'static [u8],
+    self_test: &'static [u8],
+}
+
+const JAPANESE: Phrases = Phrases {
+    up: b"ueni/mairima'_su,",
+    down: b"shitani/mairima'_su,",
+    closing: b"do'aga/shimarima'_su.",
+    stand_clear: b"gochu'ui/kudasa'i.",
+    self_test: b"te'_suto/shima'_su.",
+};
+
+const ENGLISH: Phrases = Phrases {
+    up: b"go'uingu/a'ppu,",
+    down: b"go'uingu/da'un,",
+    closing: b"do'asu/kurouzu'ingu.",
+    stand_clear: b"pu'riizu/sutando/kuri'a.",
+    self_test: b"se'rufu/te'_suto.",
+};
+
+// floors past the end are read digit by digit by the chip's NUM tag
+const ENGLISH_NUMBERS: [&str; 11] = [
+    "ze'ro",
+    "wa'n",
+    "tsu'u",
+    "su'rii",
+    "fo'a",
+    "fa'ibu",
+    "shi'kkusu",
+    "se'bun",
+    "e'ito",
+    "na'in",
+    "te'n",
+];
+
+impl Language {
+    fn phrases(self) -> &'static Phrases {
+        match self {
+            Language::Japanese => &JAPANESE,
+            Language::English => &ENGLISH,
+        }
+    }
+
+    fn floor(self, number: i8) -> alloc::string::String {
+        let is_basement = number < 0;
+        match self {
+            Language::Japanese => format!(
+                "{}<NUMK VAL={} COUNTER=kai>de'_su,",
+                if is_basement { "chi'ka/" } else { "" },
+                number.abs()
+            ),
+            Language::English => {
+                let digits = format!("<NUM VAL={}>", number.abs());
+                let name = ENGLISH_NUMBERS
+                    .get(number.unsigned_abs() as usize)
+                    .copied()
+                    .unwrap_or(&digits);
+                if is_basement {
+                    format!("be'esumento/{},", name)
+                } else {
+                    format!("fu'roa/{},", name)
+                }
+            }
+        }
+    }
+}
+
 struct Floor {
     number: i8,
     label: &'static str,
+    // shown instead of `label` by the large current floor readout
+    big_label: Option<&'static str>,
     stop: bool,
+    // urgency of the pending call, 0 for an ordinary one
+    priority: u8,
+    // false for floors the car passes through but never stops at
+    selectable: bool,
+    // inside the express zone, passed through without stopping
+    express: bool,
+    // temporarily closed, calls are refused and flashed back
+    out_of_service: bool,
     button: Box<dyn LedButtonTrait>,
+    // reports the car is level with this floor
+    level_sensor: Option<Box<dyn InputTrait>>,
+    // ticks into the acknowledgement blink, while it plays
+    ack_tick: Option<u32>,
+    // tick the current call latched at
+    latched_at: u32,
+    // ticks into the refusal flash, while it plays
+    reject_tick: Option<u32>,
+    // ticks into the flash telling a passed call it's still coming, while it plays
+    pass_tick: Option<u32>,
+    // landing call button, summons the car without choosing a direction
+    summon_button: Option<Box<dyn ButtonTrait>>,
+    // the pending call came from the landing rather than the cabin
+    summoned: bool,
+    // a pin error not yet reported as an event
+    fault: Option<ButtonError>,
+    // what the LED should show, written out by dispatch
+    led: Option<bool>,
+    // turn it off with a fade where the LED can
+    fade: bool,
+    // names this floor in its own language instead of the car's
+    language: Option<Language>,
+}
+
+impl Floor {
+    fn stoppable(&self) -> bool {
+        self.selectable && !self.express && !self.out_of_service
+    }
+
+    fn set_led(&mut self, on: bool) {
+        self.led = Some(on);
+        self.fade = false;
+    }
+
+    // served, so ease the LED off rather than snapping it
+    fn fade_led(&mut self) {
+        self.led = Some(false);
+        self.fade = true;
+    }
+
+    // a failed write is kept for dispatch to report, the car carries on
+    fn flush_led(&mut self) {
+        let written = match self.led.take() {
+            Some(false) if core::mem::take(&mut self.fade) => self.button.fade_off(),
+            Some(on) => self.button.set_on(on),
+            None => self.button.step_fade(),
+        };
+        if let Err(error) = written {
+            self.fault = Some(error);
+        }
+    }
+
+    // a failed read counts as released
+    fn press_kind(&mut self) -> Option<PressKind> {
+        match self.button.press_kind() {
+            Ok(kind) => kind,
+            Err(error) => {
+                self.fault = Some(error);
+                None
+            }
+        }
+    }
 }
 
 pub struct Elevator {
@@ -38,7 +442,116 @@ pub struct Elevator {
     door: DoorState,
     floors: [Floor; 8],
     repaint: Option<Box<dyn FnMut()>>,
+    repaint_fn: Option<fn()>,
     announce: Option<Box<dyn FnMut(&[u8])>>,
+    announce_fn: Option<fn(&[u8])>,
+    announce_format: AnnounceFormat,
+    event: Option<Box<dyn FnMut(ElevatorEvent)>>,
+    arrive: Option<Box<dyn FnMut(i8)>>,
+    idle_callback: Option<Box<dyn FnMut()>>,
+    busy_callback: Option<Box<dyn FnMut()>>,
+    // callbacks owed since the last dispatch; the buffers keep their capacity
+    pending_repaint: bool,
+    pending_events: Vec<ElevatorEvent>,
+    pending_arrival: Option<i8>,
+    pending_brightness: Option<u8>,
+    // queued phrases back to back, with where each one ends
+    pending_announcements: Vec<u8>,
+    pending_announcement_ends: Vec<usize>,
+    // last state reported to on_idle and on_busy
+    was_idle: bool,
+    events: heapless::HistoryBuffer<ElevatorEvent, EVENT_LOG_SIZE>,
+    // set on arrival, so door re-opens at the same floor don't count
+    arriving: bool,
+    brightness: Option<Box<dyn FnMut(u8)>>,
+    dimmed: bool,
+    attract_mode: bool,
+    attract: Option<usize>,
+    idle_ticks: u32,
+    travel_paused: bool,
+    overloaded: bool,
+    // ease in and out of stops instead of travelling at one speed
+    acceleration_ramp: bool,
+    // the leg being travelled started from a standstill
+    leg_from_rest: bool,
+    sound: Option<Box<dyn Sound>>,
+    travel_tone: bool,
+    // last tone played, None while silent
+    tone: Option<u32>,
+    pending_sound: Option<SoundEffect>,
+    highlighted: Option<usize>,
+    max_door_open_ms: u32,
+    door_open_ticks: u32,
+    // the doors were forced shut; open requests are refused until the car
+    // departs or door_command sees the open input released
+    door_forced_close: bool,
+    show_heartbeat: bool,
+    heartbeat: bool,
+    up_travel_ms: u32,
+    down_travel_ms: u32,
+    self_test: Option<u32>,
+    ticks: u32,
+    last_service: Option<(i8, u32)>,
+    traffic_mode: TrafficMode,
+    // dwell at a stop one floor on from the last, as a percentage of the usual
+    adjacent_dwell_percent: u8,
+    // this stop gets the shortened dwell
+    short_dwell: bool,
+    scheduler: Scheduler,
+    // a call waiting longer than this is headed for first, 0 disables it
+    max_wait_ms: u32,
+    park_index: Option<usize>,
+    forced_target: Option<usize>,
+    demo: Option<DemoTraffic>,
+    open_at_stop: bool,
+    service: ServiceState,
+    manual_up: bool,
+    manual_down: bool,
+    // name every Nth floor passed without stopping, 0 names only stops
+    announce_every: u8,
+    floors_passed: u8,
+    // slow down and flash the LED of a call passed on the way to a priority target
+    pass_acknowledge: bool,
+    // repeat the direction this often while the doors stay open, 0 disables it
+    reannounce_interval_ms: u32,
+    announce_closing: bool,
+    strict_door_safety: bool,
+    ack_pattern: Option<AckPattern>,
+    // openness the doors stop and hold at while venting
+    vent: Option<u8>,
+    quiet: bool,
+    language: Language,
+    display_mode: DisplayMode,
+    door_style: DoorStyle,
+    // arrow drawn while idle, the scheduler never sees it
+    idle_arrow: Option<Direction>,
+    // floor buttons seen down by poll_inputs, bit per floor index
+    pressed: u8,
+    // landing summon buttons seen down, same layout as `pressed`
+    summons: u8,
+    // floor buttons held for a long press, which cancels the call
+    long_pressed: u8,
+    turbo: bool,
+    // minute of the day and the tick it was set at
+    clock: Option<(u16, u32)>,
+    // (from minute, until minute, floor) windows for parking
+    parking_schedule: heapless::Vec<(u16, u16, i8), PARKING_WINDOWS>,
+    announce_open_at: u8,
+    open_announce_pending: bool,
+    leveling_ms: u32,
+    // ticks left before the doors release after an arrival
+    leveling: Option<u32>,
+    // outstanding HoldTokens
+    holds: Rc<Cell<u32>>,
+    // the doors have stayed closed for a full tick
+    door_settled: bool,
+    comm_watchdog_ms: Option<u32>,
+    comm_ticks: u32,
+    level_mismatches: u32,
+    floor_heights_mm: Option<[i32; 8]>,
+    // set by any visible change, cleared by draw
+    dirty: Cell<bool>,
+    startup_message: Option<&'static [u8]>,
 }
 
 impl Elevator {
@@ -52,46 +565,432 @@ impl Elevator {
             floors: floors.map(|(number, label, button)| Floor {
                 number,
                 label,
+                big_label: None,
                 stop: false,
+                priority: 0,
+                selectable: true,
+                express: false,
+                out_of_service: false,
                 button,
+                level_sensor: None,
+                ack_tick: None,
+                latched_at: 0,
+                reject_tick: None,
+                pass_tick: None,
+                summon_button: None,
+                summoned: false,
+                fault: None,
+                led: None,
+                fade: false,
+                language: None,
             }),
             repaint: None,
+            repaint_fn: None,
             announce: None,
+            announce_fn: None,
+            announce_format: AnnounceFormat::default(),
+            event: None,
+            arrive: None,
+            idle_callback: None,
+            busy_callback: None,
+            pending_repaint: false,
+            pending_events: Vec::new(),
+            pending_arrival: None,
+            pending_brightness: None,
+            pending_announcements: Vec::new(),
+            pending_announcement_ends: Vec::new(),
+            was_idle: true,
+            events: heapless::HistoryBuffer::new(),
+            arriving: false,
+            brightness: None,
+            dimmed: false,
+            attract_mode: false,
+            attract: None,
+            idle_ticks: 0,
+            travel_paused: false,
+            overloaded: false,
+            acceleration_ramp: false,
+            leg_from_rest: false,
+            sound: None,
+            travel_tone: false,
+            tone: None,
+            pending_sound: None,
+            highlighted: None,
+            max_door_open_ms: u32::MAX,
+            door_open_ticks: 0,
+            door_forced_close: false,
+            show_heartbeat: false,
+            heartbeat: false,
+            up_travel_ms: DEFAULT_TRAVEL_MS,
+            down_travel_ms: DEFAULT_TRAVEL_MS,
+            self_test: None,
+            ticks: 0,
+            last_service: None,
+            traffic_mode: TrafficMode::Normal,
+            adjacent_dwell_percent: 100,
+            short_dwell: false,
+            scheduler: Scheduler::Look,
+            max_wait_ms: 0,
+            park_index: None,
+            forced_target: None,
+            demo: None,
+            open_at_stop: false,
+            service: ServiceState::Normal,
+            manual_up: false,
+            manual_down: false,
+            announce_every: 0,
+            reannounce_interval_ms: 0,
+            announce_closing: true,
+            floors_passed: 0,
+            pass_acknowledge: false,
+            strict_door_safety: false,
+            ack_pattern: None,
+            vent: None,
+            quiet: false,
+            language: Language::Japanese,
+            display_mode: DisplayMode::Normal,
+            door_style: DoorStyle::CenterOpening,
+            idle_arrow: None,
+            pressed: 0,
+            summons: 0,
+            long_pressed: 0,
+            turbo: false,
+            clock: None,
+            parking_schedule: heapless::Vec::new(),
+            announce_open_at: 0,
+            open_announce_pending: false,
+            leveling_ms: 0,
+            leveling: None,
+            holds: Rc::new(Cell::new(0)),
+            door_settled: true,
+            comm_watchdog_ms: None,
+            comm_ticks: 0,
+            level_mismatches: 0,
+            floor_heights_mm: None,
+            dirty: Cell::new(true),
+            startup_message: None,
+        }
+    }
+
+    // the car still travels through the floor but it can never be called
+    pub fn skip_floor(mut self, floor: i8) -> Self {
+        let index = self.floor_to_index(floor);
+        self.floors[index].selectable = false;
+        self
+    }
+
+    // floors strictly between `from` and `to` are passed without stopping;
+    // their pending calls are dropped
+    pub fn set_express_zone(&mut self, from: i8, to: i8) {
+        let (low, high) = (from.min(to), from.max(to));
+        for floor in self.floors.iter_mut() {
+            floor.express = floor.number > low && floor.number < high;
+            if floor.express && floor.stop {
+                floor.stop = false;
+                floor.set_led(false);
+            }
+        }
+        self.request_repaint();
+    }
+
+    // takes a single floor out of service, dropping its pending call;
+    // the car passes it until it's back
+    pub fn set_floor_service(&mut self, floor: i8, in_service: bool) -> bool {
+        let Some(index) = self.try_floor_to_index(floor) else {
+            return false;
+        };
+        let floor = &mut self.floors[index];
+        floor.out_of_service = !in_service;
+        if !in_service {
+            if floor.stop {
+                floor.stop = false;
+                floor.set_led(false);
+            }
+            if self.forced_target == Some(index) {
+                self.forced_target = None;
+            }
+            if self.park_index == Some(index) {
+                self.park_index = None;
+            }
+        }
+        self.request_repaint();
+        true
+    }
+
+    pub fn clear_express_zone(&mut self) {
+        for floor in self.floors.iter_mut() {
+            floor.express = false;
+        }
+        self.request_repaint();
+    }
+
+    // renames a floor on the list, e.g. "3" to "3F"; labels are right
+    // aligned, so up to two characters fit the highlight box
+    pub fn set_label(&mut self, floor: i8, label: &'static str) -> bool {
+        match self.try_floor_to_index(floor) {
+            Some(index) => {
+                self.floors[index].label = label;
+                self.request_repaint();
+                true
+            }
+            None => false,
         }
     }
 
+    pub fn with_big_label(mut self, floor: i8, label: &'static str) -> Self {
+        let index = self.floor_to_index(floor);
+        self.floors[index].big_label = Some(label);
+        self
+    }
+
+    // names `floor` in `language` whatever set_language says, e.g. for an
+    // international arrivals floor; only its name, the other phrases follow the car
+    pub fn with_floor_language(mut self, floor: i8, language: Language) -> Self {
+        let index = self.floor_to_index(floor);
+        self.floors[index].language = Some(language);
+        self
+    }
+
+    pub fn with_summon_button<S>(mut self, floor: i8, button: S) -> Self
+    where
+        S: ButtonTrait + 'static,
+    {
+        let index = self.floor_to_index(floor);
+        self.floors[index].summon_button = Some(Box::new(button));
+        self
+    }
+
+    pub fn with_level_sensor<S>(mut self, floor: i8, sensor: S) -> Self
+    where
+        S: InputTrait + 'static,
+    {
+        let index = self.floor_to_index(floor);
+        self.floors[index].level_sensor = Some(Box::new(sensor));
+        self
+    }
+
+    // shaft position of each floor, bottom floor first, for driving a real car
+    pub fn with_floor_heights_mm(mut self, heights: [i32; 8]) -> Self {
+        self.floor_heights_mm = Some(heights);
+        self
+    }
+
     fn set_direction(&mut self, direction: Direction) {
         if self.direction == direction {
             return;
         }
+        let previous = self.direction;
+        let was_moving = self.is_moving();
         self.direction = direction;
-        if let Some(callback) = &mut self.repaint {
+        if matches!(direction, Direction::Up(Some(0)) | Direction::Down(Some(0))) {
+            self.leg_from_rest = !was_moving;
+        }
+        // the car must never travel unless the doors are closed
+        debug_assert!(
+            !self.is_moving() || self.door == DoorState::Closed,
+            "car moving with the doors not closed"
+        );
+        self.request_repaint();
+        // progress changes every tick, only report changes of heading
+        if discriminant(&previous) != discriminant(&direction) {
+            self.emit(ElevatorEvent::DirectionChanged(direction));
+        }
+        if !was_moving && self.is_moving() {
+            // a jammed open button can only reopen the doors once the car has left
+            self.door_forced_close = false;
+            self.emit(ElevatorEvent::Departed(direction));
+        }
+        if direction == Direction::Idle {
+            // turbo only lasts for the trip
+            self.turbo = false;
+        }
+    }
+
+    fn dwell_step(&self) -> u8 {
+        if !self.short_dwell {
+            return DOOR_OPEN_STEP;
+        }
+        let percent = self.adjacent_dwell_percent.max(1) as u32;
+        (DOOR_OPEN_STEP as u32 * 100 / percent).min(100) as u8
+    }
+
+    // fires on_idle or on_busy when the car settles or wakes up
+    fn notify_idle(&mut self) {
+        let idle = self.is_idle();
+        if idle == self.was_idle {
+            return;
+        }
+        self.was_idle = idle;
+        let callback = if idle {
+            &mut self.idle_callback
+        } else {
+            &mut self.busy_callback
+        };
+        if let Some(callback) = callback {
             callback();
         }
     }
 
+    // progress per tick for a travel phase lasting `ms`, doubled in turbo
+    fn travel_increment(&self, ms: u32) -> u8 {
+        let step = travel_step(ms);
+        if self.turbo {
+            step.saturating_mul(2).min(100)
+        } else {
+            step
+        }
+    }
+
+    // the next opening progress, stopping exactly at the vent position if one is set
+    fn opening_step(&self, progress: u8) -> u8 {
+        let next = progress + DOOR_MOVE_STEP;
+        self.vent.map_or(next, |vent| next.min(vent))
+    }
+
+    // a pending call the car is going past for a priority target
+    fn passes_call(&self, index: usize) -> bool {
+        self.floors[index].stop && self.priority_target().is_some_and(|target| target != index)
+    }
+
+    // the car comes to a halt on reaching this floor
+    fn stops_at(&self, index: usize) -> bool {
+        let served =
+            self.floors[index].stop && self.priority_target().is_none_or(|target| target == index);
+        served || self.park_index == Some(index) || index == 0 || index == self.floors.len() - 1
+    }
+
+    // the floor being travelled towards and the progress so far
+    fn next_leg(&self) -> Option<(usize, u8)> {
+        let index = self.current_floor_index;
+        match self.direction {
+            Direction::Up(Some(progress)) if index + 1 < self.floors.len() => {
+                Some((index + 1, progress))
+            }
+            Direction::Down(Some(progress)) if index > 0 => Some((index - 1, progress)),
+            _ => None,
+        }
+    }
+
+    // percent of full speed `progress` into the leg towards `next`: ramped up
+    // from a standstill and down before a stop, and halved for the half floor
+    // either side of a call being passed, when those are acknowledged
+    fn travel_speed(&self, progress: u8, next: usize) -> u8 {
+        let progress = progress.min(100);
+        let mut speed = 100;
+        if self.acceleration_ramp {
+            let ramp = |into: u8| {
+                let gained = (100 - RAMP_MIN_SPEED) as u32 * into as u32 / RAMP_PROGRESS as u32;
+                (RAMP_MIN_SPEED as u32 + gained).min(100) as u8
+            };
+            if self.leg_from_rest {
+                speed = speed.min(ramp(progress));
+            }
+            if self.stops_at(next) {
+                speed = speed.min(ramp(100 - progress));
+            }
+        }
+        let near = if progress < 50 {
+            self.current_floor_index
+        } else {
+            next
+        };
+        if self.pass_acknowledge && self.passes_call(near) {
+            speed /= 2;
+        }
+        speed
+    }
+
+    // travel_increment scaled by the speed at this point of the leg
+    fn leg_increment(&self, ms: u32, progress: u8, next: usize) -> u8 {
+        let step = self.travel_increment(ms) as u32;
+        (step * self.travel_speed(progress, next) as u32 / 100).max(1) as u8
+    }
+
     fn set_door(&mut self, door: DoorState) {
         if self.door == door {
             return;
         }
+        let previous = self.door;
         self.door = door;
-        if let Some(callback) = &mut self.repaint {
-            callback();
+        if matches!(door, DoorState::Opening(_)) {
+            // opened early, e.g. by the open button, so the pending release is moot
+            self.leveling = None;
+        }
+        debug_assert!(
+            !self.is_moving() || self.door == DoorState::Closed,
+            "doors moving while the car is travelling"
+        );
+        self.request_repaint();
+        match (previous, door) {
+            (DoorState::Opening(_), DoorState::Open(_)) => {
+                self.emit(ElevatorEvent::DoorOpened(self.current_floor()));
+            }
+            (_, DoorState::Closed) => {
+                self.door_settled = false;
+                self.emit(ElevatorEvent::DoorClosed(self.current_floor()));
+            }
+            _ => {}
         }
     }
 
+    fn request_repaint(&mut self) {
+        self.dirty.set(true);
+        self.pending_repaint = true;
+    }
+
+    fn emit(&mut self, event: ElevatorEvent) {
+        self.events.write(event);
+        self.pending_events.push(event);
+    }
+
     fn set_current_floor_index(&mut self, index: usize) {
         if self.current_floor_index == index {
             return;
         }
         self.current_floor_index = index;
-        if let Some(callback) = &mut self.repaint {
-            callback();
+        self.request_repaint();
+    }
+
+    fn announce(&mut self, message: &[u8]) {
+        if self.quiet {
+            return;
+        }
+        self.pending_announcements.extend_from_slice(message);
+        self.pending_announcement_ends
+            .push(self.pending_announcements.len());
+    }
+
+    fn speak(&mut self, message: &[u8]) {
+        if let Some(callback) = &mut self.announce {
+            let format = &self.announce_format;
+            let mut framed =
+                Vec::with_capacity(format.prefix.len() + message.len() + format.terminator.len());
+            framed.extend_from_slice(format.prefix);
+            framed.extend_from_slice(message);
+            framed.extend_from_slice(format.terminator);
+            callback(&framed);
+        }
+        if let Some(callback) = self.announce_fn {
+            // written piece by piece, so no buffer is allocated for the framing
+            for piece in [
+                self.announce_format.prefix,
+                message,
+                self.announce_format.terminator,
+            ] {
+                if !piece.is_empty() {
+                    callback(piece);
+                }
+            }
         }
     }
 
     fn goto_next_floor(&mut self) {
         let index = self.current_floor_index;
+        if self.floors[index].stop {
+            // called again just as the doors shut, open up instead of leaving
+            self.set_door(DoorState::Opening(0));
+            return;
+        }
         let upper_floors = &self.floors[index..];
         let lower_floors = &self.floors[..index];
         let direction = match self.direction {
@@ -113,118 +1012,892 @@ impl Elevator {
                     Direction::Idle
                 }
             }
-            Direction::Idle => {
-                let upper = upper_floors.iter().position(|f| f.stop);
-                let lower = lower_floors.iter().position(|f| f.stop);
-                match (upper, lower) {
-                    (Some(_), Some(_)) => {
-                        if upper.unwrap() - index < index - lower.unwrap() {
-                            Direction::Up(Some(0))
-                        } else {
-                            Direction::Down(Some(0))
-                        }
-                    }
-                    (Some(_), _) => Direction::Up(Some(0)),
-                    (_, Some(_)) => Direction::Down(Some(0)),
-                    _ => Direction::Idle,
-                }
-            }
+            Direction::Idle => match self.idle_direction() {
+                Direction::Idle => self.park_direction(),
+                direction => direction,
+            },
+        };
+        let direction = match self.scheduler {
+            Scheduler::Cost => match self.cost_direction() {
+                Direction::Idle => direction,
+                cost_direction => cost_direction,
+            },
+            Scheduler::Look => direction,
+        };
+        // don't let a far call starve behind a stream of near ones
+        let direction = match self.starved_target() {
+            Some(target) if target > index => Direction::Up(Some(0)),
+            Some(target) if target < index => Direction::Down(Some(0)),
+            _ => direction,
+        };
+        // a goto target or urgent call is served first, whichever way the car was heading
+        let direction = match self.priority_target() {
+            Some(target) if target > index => Direction::Up(Some(0)),
+            Some(target) if target < index => Direction::Down(Some(0)),
+            _ => direction,
         };
 
-        match direction {
-            Direction::Up(_) => {
-                if let Some(callback) = &mut self.announce {
-                    callback(b"ueni/mairima'_su,\r");
+        self.announce_direction(direction);
+        self.set_direction(direction);
+    }
+
+    // the direction an idle car would leave in to serve the pending calls
+    fn idle_direction(&self) -> Direction {
+        let index = self.current_floor_index;
+        // distances to the nearest call above and below
+        let upper = self.floors[index..].iter().position(|f| f.stop);
+        let lower = self.floors[..index]
+            .iter()
+            .rposition(|f| f.stop)
+            .map(|i| index - i);
+        match (upper, lower) {
+            (Some(upper), Some(lower)) => {
+                if self.traffic_mode != TrafficMode::DownPeak && upper < lower {
+                    Direction::Up(Some(0))
+                } else {
+                    Direction::Down(Some(0))
                 }
             }
-            Direction::Down(_) => {
-                if let Some(callback) = &mut self.announce {
-                    callback(b"shitani/mairima'_su,\r");
+            (Some(_), _) => Direction::Up(Some(0)),
+            (_, Some(_)) => Direction::Down(Some(0)),
+            _ => Direction::Idle,
+        }
+    }
+
+    // compares going up first against going down first; each pending call
+    // costs the floors travelled before reaching it, weighted by its age
+    fn cost_direction(&self) -> Direction {
+        let index = self.current_floor_index;
+        let top = self.floors[index + 1..]
+            .iter()
+            .rposition(|f| f.stop)
+            .map(|i| index + 1 + i);
+        let bottom = self.floors[..index].iter().position(|f| f.stop);
+        let cost = |up_first: bool| -> u32 {
+            let turn = if up_first { top } else { bottom }.unwrap_or(index);
+            self.floors
+                .iter()
+                .enumerate()
+                .filter(|(i, f)| f.stop && *i != index)
+                .map(|(i, f)| {
+                    let distance = if (i > index) == up_first {
+                        i.abs_diff(index)
+                    } else {
+                        turn.abs_diff(index) + turn.abs_diff(i)
+                    };
+                    let age = self.ticks.wrapping_sub(f.latched_at);
+                    (distance as u32).saturating_mul(age.saturating_add(1))
+                })
+                .fold(0, u32::saturating_add)
+        };
+        match (top, bottom) {
+            (Some(_), Some(_)) => {
+                if cost(true) <= cost(false) {
+                    Direction::Up(Some(0))
+                } else {
+                    Direction::Down(Some(0))
                 }
             }
-            Direction::Idle => {}
+            (Some(_), None) => Direction::Up(Some(0)),
+            (None, Some(_)) => Direction::Down(Some(0)),
+            (None, None) => Direction::Idle,
         }
-        self.set_direction(direction);
     }
 
-    pub fn advance(&mut self) {
-        // check if button is clicked
-        for (index, floor) in self.floors.iter_mut().enumerate() {
-            if floor.button.is_pressed().unwrap() {
-                if !floor.stop {
-                    floor.stop = true;
-                    floor.button.turn_on().unwrap();
-                    if self.direction == Direction::Idle && self.current_floor_index == index {
-                        self.set_door(DoorState::Opening(0));
-                        return;
-                    }
-                }
+    // where the car must go next regardless of LOOK order: a goto target, else
+    // the most urgent pending priority call, the nearest one on ties
+    fn priority_target(&self) -> Option<usize> {
+        let index = self.current_floor_index;
+        self.forced_target.or_else(|| {
+            self.floors
+                .iter()
+                .enumerate()
+                .filter(|(_, f)| f.stop && f.priority > 0)
+                .max_by_key(|(i, f)| (f.priority, core::cmp::Reverse(i.abs_diff(index))))
+                .map(|(i, _)| i)
+        })
+    }
+
+    // the oldest call once it has waited past max_wait_ms
+    fn starved_target(&self) -> Option<usize> {
+        let limit = self.max_wait_ms / TICK_MS;
+        if limit == 0 {
+            return None;
+        }
+        self.floors
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.stop)
+            .map(|(i, f)| (i, self.ticks.wrapping_sub(f.latched_at)))
+            .filter(|(_, age)| *age >= limit)
+            .max_by_key(|(_, age)| *age)
+            .map(|(i, _)| i)
+    }
+
+    fn update_priority_leds(&mut self) {
+        let lit = (self.ticks / PRIORITY_BLINK).is_multiple_of(2);
+        for floor in self.floors.iter_mut() {
+            if floor.stop
+                && floor.priority > 0
+                && floor.ack_tick.is_none()
+                && floor.pass_tick.is_none()
+            {
+                floor.set_led(lit);
             }
         }
-        // while door is moving, do it
-        match self.door {
-            DoorState::Opening(progress) => {
-                match progress {
-                    100 => {
-                        self.set_door(DoorState::Open(0));
+    }
+
+    fn lobby_index(&self) -> usize {
+        self.floor_to_index(1)
+    }
+
+    // a scheduled parking floor for the time of day, else the lobby during up-peak
+    fn park_floor_index(&self) -> Option<usize> {
+        let scheduled = self.time_of_day().and_then(|now| {
+            self.parking_schedule
+                .iter()
+                .find(|(start, end, _)| {
+                    if start <= end {
+                        (*start..*end).contains(&now)
+                    } else {
+                        // the window wraps past midnight
+                        now >= *start || now < *end
                     }
-                    0 => {
-                        if let Some(callback) = &mut self.announce {
-                            let floor = &self.floors[self.current_floor_index];
-                            let is_basement = floor.number < 0;
-                            callback(
-                                format!(
-                                    "{}<NUMK VAL={} COUNTER=kai>de'_su,\r",
-                                    if is_basement { "chi'ka/" } else { "" },
-                                    floor.number.abs()
-                                )
-                                .as_bytes(),
-                            );
+                })
+                .and_then(|(_, _, floor)| self.try_floor_to_index(*floor))
+        });
+        match scheduled {
+            Some(index) => Some(index),
+            None if self.traffic_mode == TrafficMode::UpPeak => Some(self.lobby_index()),
+            None => None,
+        }
+    }
+
+    // during up-peak an idle car returns to the lobby without opening its doors
+    fn park_direction(&mut self) -> Direction {
+        let Some(park) = self.park_floor_index() else {
+            return Direction::Idle;
+        };
+        if self.idle_ticks < PARK_DELAY || self.current_floor_index == park {
+            return Direction::Idle;
+        }
+        self.park_index = Some(park);
+        if park > self.current_floor_index {
+            Direction::Up(Some(0))
+        } else {
+            Direction::Down(Some(0))
+        }
+    }
+
+    // travelling between floors, rather than stopped with a direction in mind
+    pub fn is_moving(&self) -> bool {
+        matches!(
+            self.direction,
+            Direction::Up(Some(_)) | Direction::Down(Some(_))
+        )
+    }
+
+    // parked with the doors shut and nothing to do; still false while the
+    // doors of an arrival are opening
+    pub fn is_idle(&self) -> bool {
+        self.direction == Direction::Idle && self.door == DoorState::Closed && !self.has_calls()
+    }
+
+    fn has_calls(&self) -> bool {
+        self.floors.iter().any(|f| f.stop)
+    }
+
+    // drop every call and bring the car to the lobby with its doors open
+    fn start_recall(&mut self, comm_loss: bool) {
+        self.service = ServiceState::Recall { comm_loss };
+        self.park_index = None;
+        self.forced_target = None;
+        self.vent = None;
+        for floor in self.floors.iter_mut() {
+            floor.stop = false;
+            floor.set_led(false);
+        }
+        let lobby = self.lobby_index();
+        if self.current_floor_index == lobby {
+            if let DoorState::Closing(progress) = self.door {
+                // closing would drop the lobby stop, open back up instead
+                self.set_door(DoorState::Opening(100 - progress.min(100)));
+                return;
+            }
+        }
+        self.latch_call(lobby);
+    }
+
+    fn announce_floor(&mut self, number: i8) {
+        let language = self
+            .try_floor_to_index(number)
+            .and_then(|index| self.floors[index].language)
+            .unwrap_or(self.language);
+        let message = language.floor(number);
+        self.announce(message.as_bytes());
+    }
+
+    fn announce_direction(&mut self, direction: Direction) {
+        let phrases = self.language.phrases();
+        match direction {
+            Direction::Up(_) => {
+                self.announce(phrases.up);
+            }
+            Direction::Down(_) => {
+                self.announce(phrases.down);
+            }
+            Direction::Idle => {}
+        }
+    }
+
+    // names a floor the car moves past without stopping
+    fn announce_passing(&mut self, index: usize) {
+        let stopping =
+            self.floors[index].stop && self.priority_target().is_none_or(|target| target == index);
+        if stopping {
+            self.floors_passed = 0;
+            return;
+        }
+        self.floors_passed = self.floors_passed.wrapping_add(1);
+        if self.announce_every > 0 && self.floors_passed.is_multiple_of(self.announce_every) {
+            self.announce_floor(self.floors[index].number);
+        }
+    }
+
+    fn open_on_arrival(&mut self) {
+        let ticks = self.leveling_ms / TICK_MS;
+        if ticks == 0 {
+            self.set_door(DoorState::Opening(0));
+        } else {
+            self.leveling = Some(ticks);
+        }
+    }
+
+    // moves one floor at a time while a direction is held, stopping level on release
+    fn update_manual(&mut self) {
+        match self.direction {
+            Direction::Up(Some(progress)) if progress < 100 => {
+                let progress = progress + self.travel_increment(self.up_travel_ms);
+                self.set_direction(Direction::Up(Some(progress.min(100))));
+                return;
+            }
+            Direction::Down(Some(progress)) if progress < 100 => {
+                let progress = progress + self.travel_increment(self.down_travel_ms);
+                self.set_direction(Direction::Down(Some(progress.min(100))));
+                return;
+            }
+            Direction::Up(Some(_)) => {
+                self.set_current_floor_index(self.current_floor_index + 1);
+            }
+            Direction::Down(Some(_)) => {
+                self.set_current_floor_index(self.current_floor_index - 1);
+            }
+            _ => {}
+        }
+        let index = self.current_floor_index;
+        let direction = if self.manual_up && index + 1 < self.floors.len() {
+            Direction::Up(Some(0))
+        } else if self.manual_down && index > 0 {
+            Direction::Down(Some(0))
+        } else {
+            Direction::Idle
+        };
+        self.set_direction(direction);
+    }
+
+    // while stopped, trust a level sensor over the modelled position
+    fn reconcile_level(&mut self) {
+        if self.is_moving() {
+            return;
+        }
+        let level = self.floors.iter().position(|f| match &f.level_sensor {
+            Some(sensor) => sensor.is_active() == Ok(true),
+            None => false,
+        });
+        if let Some(index) = level {
+            if index != self.current_floor_index {
+                self.level_mismatches = self.level_mismatches.saturating_add(1);
+                self.set_current_floor_index(index);
+                self.emit(ElevatorEvent::LevelCorrected(self.current_floor()));
+            }
+        }
+    }
+
+    // the only place idle time accrues: it counts from the first tick the car is
+    // stopped with closed doors and nothing to serve, and latch_call resets it
+    fn update_idle(&mut self) {
+        if !self.is_idle() {
+            self.idle_ticks = 0;
+        } else {
+            self.idle_ticks = self.idle_ticks.saturating_add(1);
+        }
+    }
+
+    // dim the display once the car has been idle for a while
+    fn update_brightness(&mut self) {
+        let dimmed = self.idle_ticks >= DIM_DELAY;
+        if self.dimmed != dimmed {
+            self.dimmed = dimmed;
+            self.pending_brightness = Some(if dimmed {
+                DIM_CONTRAST
+            } else {
+                BRIGHT_CONTRAST
+            });
+        }
+    }
+
+    fn update_attract(&mut self) {
+        if self.idle_ticks == 0 {
+            self.stop_attract();
+            return;
+        }
+        if self.idle_ticks < ATTRACT_DELAY {
+            return;
+        }
+        // sweep up then down, moving one floor every ATTRACT_STEP ticks
+        match self.attract {
+            None => {
+                for floor in self.floors.iter_mut() {
+                    floor.set_led(false);
+                }
+                self.floors[0].set_led(true);
+                self.attract = Some(0);
+            }
+            Some(phase) if (self.idle_ticks - ATTRACT_DELAY).is_multiple_of(ATTRACT_STEP) => {
+                let len = self.floors.len();
+                let index = |phase: usize| {
+                    if phase < len {
+                        phase
+                    } else {
+                        len * 2 - 2 - phase
+                    }
+                };
+                let next = (phase + 1) % (len * 2 - 2);
+                self.floors[index(phase)].set_led(false);
+                self.floors[index(next)].set_led(true);
+                self.attract = Some(next);
+            }
+            Some(_) => {}
+        }
+    }
+
+    fn update_demo(&mut self) {
+        let idle_ticks = self.idle_ticks;
+        let Some(demo) = &mut self.demo else {
+            return;
+        };
+        if demo.paused {
+            if idle_ticks >= DEMO_RESUME_DELAY {
+                demo.paused = false;
+                demo.countdown = demo.interval;
+            }
+            return;
+        }
+        demo.countdown -= 1;
+        if demo.countdown > 0 {
+            return;
+        }
+        demo.countdown = demo.interval;
+        let index = demo.rng.next_u32() as usize % self.floors.len();
+        self.call_floor(self.floors[index].number);
+    }
+
+    fn update_ack(&mut self) {
+        let Some(pattern) = self.ack_pattern else {
+            return;
+        };
+        let step_ticks = pattern.step_ticks.max(1);
+        for floor in self.floors.iter_mut() {
+            let Some(tick) = floor.ack_tick else {
+                continue;
+            };
+            let phase = tick / step_ticks;
+            if !floor.stop || phase >= pattern.blinks * 2 {
+                // served or done, leave the LED showing the call
+                floor.ack_tick = None;
+                floor.set_led(floor.stop);
+            } else {
+                floor.set_led(phase % 2 == 0);
+                floor.ack_tick = Some(tick + 1);
+            }
+        }
+    }
+
+    fn update_reject(&mut self) {
+        for floor in self.floors.iter_mut() {
+            let Some(tick) = floor.reject_tick else {
+                continue;
+            };
+            let phase = tick / PRIORITY_BLINK;
+            if phase >= REJECT_BLINKS * 2 {
+                floor.reject_tick = None;
+                floor.set_led(floor.stop);
+            } else {
+                floor.set_led(phase.is_multiple_of(2));
+                floor.reject_tick = Some(tick + 1);
+            }
+        }
+    }
+
+    fn update_passed(&mut self) {
+        for floor in self.floors.iter_mut() {
+            let Some(tick) = floor.pass_tick else {
+                continue;
+            };
+            let phase = tick / PRIORITY_BLINK;
+            if !floor.stop || phase >= PASS_BLINKS * 2 {
+                floor.pass_tick = None;
+                floor.set_led(floor.stop);
+            } else {
+                // dark first, so the flash stands out from the lit call
+                floor.set_led(phase % 2 == 1);
+                floor.pass_tick = Some(tick + 1);
+            }
+        }
+    }
+
+    // the travelling tone follows the speed, started and stopped right away but
+    // otherwise changed at most every TONE_UPDATE_TICKS
+    fn update_tone(&mut self) {
+        if !self.travel_tone {
+            return;
+        }
+        let tone = self.next_leg().map(|(next, progress)| {
+            let speed = self.travel_speed(progress, next) as u32;
+            TONE_MIN_HZ + (TONE_MAX_HZ - TONE_MIN_HZ) * speed / 100
+        });
+        let due = self.ticks.is_multiple_of(TONE_UPDATE_TICKS);
+        if tone == self.tone || (tone.is_some() && self.tone.is_some() && !due) {
+            return;
+        }
+        self.tone = tone;
+        self.pending_sound = Some(tone.map_or(SoundEffect::Silence, SoundEffect::Travel));
+    }
+
+    fn stop_attract(&mut self) {
+        if self.attract.take().is_some() {
+            for floor in self.floors.iter_mut() {
+                floor.set_led(floor.stop);
+            }
+        }
+    }
+
+    // drops a pending call, e.g. one pressed by mistake
+    fn cancel_call(&mut self, index: usize) {
+        let floor = &mut self.floors[index];
+        if !floor.stop {
+            return;
+        }
+        floor.stop = false;
+        floor.ack_tick = None;
+        floor.set_led(false);
+        if self.forced_target == Some(index) {
+            self.forced_target = None;
+        }
+        self.request_repaint();
+    }
+
+    // a landing call, served like any other stop but remembered as one
+    fn summon_index(&mut self, index: usize) -> bool {
+        let waiting = self.floors[index].stop;
+        let opened = self.latch_call(index);
+        if !waiting && self.floors[index].stop {
+            self.floors[index].summoned = true;
+        }
+        opened
+    }
+
+    // returns true if the call opened the doors right away
+    fn latch_call(&mut self, index: usize) -> bool {
+        if matches!(self.service, ServiceState::ShuttingDown | ServiceState::Off) {
+            return false;
+        }
+        if self.floors[index].out_of_service {
+            self.floors[index].reject_tick.get_or_insert(0);
+            return false;
+        }
+        if self.floors[index].stop || !self.floors[index].stoppable() {
+            return false;
+        }
+        // already being served, the doors are open at this floor
+        if index == self.current_floor_index
+            && matches!(self.door, DoorState::Opening(_) | DoorState::Open(_))
+        {
+            return false;
+        }
+        self.stop_attract();
+        self.park_index = None;
+        self.idle_ticks = 0;
+        let floor = &mut self.floors[index];
+        floor.stop = true;
+        floor.summoned = false;
+        floor.priority = 0;
+        floor.latched_at = self.ticks;
+        floor.set_led(true);
+        if self.ack_pattern.is_some() {
+            floor.ack_tick = Some(0);
+        }
+        self.request_repaint();
+        if self.direction == Direction::Idle && self.current_floor_index == index {
+            self.set_door(DoorState::Opening(0));
+            return true;
+        }
+        false
+    }
+
+    // lights every floor LED in turn, then chimes and cycles the doors once
+    fn update_self_test(&mut self, tick: u32) {
+        let index = (tick / SELF_TEST_STEP) as usize;
+        if !tick.is_multiple_of(SELF_TEST_STEP) {
+            self.self_test = Some(tick + 1);
+            return;
+        }
+        if index > 0 {
+            self.floors[index - 1].set_led(false);
+        }
+        if index < self.floors.len() {
+            self.floors[index].set_led(true);
+            self.self_test = Some(tick + 1);
+        } else {
+            self.self_test = None;
+            self.announce(self.language.phrases().self_test);
+            self.set_door(DoorState::Opening(0));
+        }
+    }
+
+    pub fn advance(&mut self) {
+        self.poll_inputs();
+        self.update();
+        self.dispatch();
+    }
+
+    // input phase: latches which floor buttons are down, nothing else changes
+    pub fn poll_inputs(&mut self) {
+        for (index, floor) in self.floors.iter_mut().enumerate() {
+            match floor.press_kind() {
+                Some(PressKind::Short) => self.pressed |= 1 << index,
+                Some(PressKind::Long) => self.long_pressed |= 1 << index,
+                None => {}
+            }
+            let summon = floor
+                .summon_button
+                .as_ref()
+                .map(|summon| summon.is_pressed());
+            match summon {
+                Some(Ok(true)) => self.summons |= 1 << index,
+                Some(Err(error)) => floor.fault = Some(error),
+                _ => {}
+            }
+        }
+    }
+
+    // logs a failure on a pin the elevator doesn't own, like the door buttons
+    pub fn report_pin_fault(&mut self, error: ButtonError) {
+        self.emit(ElevatorEvent::PinFault(None, error));
+    }
+
+    // there's nowhere to answer a serial command, so a failed one is only logged
+    pub fn report_command_error(&mut self, error: ParseError) {
+        self.emit(ElevatorEvent::CommandRejected(error));
+    }
+
+    // queues a press for the next update, as if the floor button were down
+    pub fn inject_press(&mut self, floor: i8) -> bool {
+        match self.try_floor_to_index(floor) {
+            Some(index) => {
+                self.pressed |= 1 << index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // logic phase: consumes the polled presses, steps the state machine and the
+    // LED animations; LED writes, announcements and callbacks are only queued
+    // for dispatch
+    pub fn update(&mut self) {
+        self.step();
+        // the self-test drives the LEDs itself
+        if self.self_test.is_none() && self.service != ServiceState::Off {
+            self.update_brightness();
+            if self.attract_mode {
+                self.update_attract();
+            }
+            self.update_ack();
+            self.update_priority_leds();
+            self.update_reject();
+            self.update_passed();
+        }
+    }
+
+    fn step(&mut self) {
+        let pressed = core::mem::take(&mut self.pressed);
+        let summons = core::mem::take(&mut self.summons);
+        let long_pressed = core::mem::take(&mut self.long_pressed);
+        if self.service == ServiceState::Off {
+            return;
+        }
+        self.announce_startup();
+        self.heartbeat = !self.heartbeat;
+        if self.show_heartbeat {
+            self.dirty.set(true);
+        }
+        self.ticks = self.ticks.wrapping_add(1);
+        if let Some(tick) = self.self_test {
+            self.update_self_test(tick);
+            return;
+        }
+        if self.service == ServiceState::ShuttingDown
+            && self.direction == Direction::Idle
+            && self.door == DoorState::Closed
+            && self.leveling.is_none()
+            && !self.has_calls()
+        {
+            // parked with the doors shut, switch everything off
+            for floor in self.floors.iter_mut() {
+                floor.ack_tick = None;
+                floor.reject_tick = None;
+                floor.pass_tick = None;
+                floor.set_led(false);
+            }
+            self.service = ServiceState::Off;
+            self.request_repaint();
+            return;
+        }
+        // never sit idle with the doors shut while a call is still latched
+        if self.direction == Direction::Idle
+            && self.door == DoorState::Closed
+            && self.leveling.is_none()
+            && (self.door_settled || !self.strict_door_safety)
+            && self.has_calls()
+        {
+            if self.floors[self.current_floor_index].stop {
+                self.set_door(DoorState::Opening(0));
+            } else {
+                self.goto_next_floor();
+            }
+        }
+        if let Some(limit) = self.comm_watchdog_ms {
+            self.comm_ticks = self.comm_ticks.saturating_add(1);
+            if self.comm_ticks >= limit / TICK_MS && self.service == ServiceState::Normal {
+                self.start_recall(true);
+            }
+        }
+        // check if button is clicked
+        if self.service == ServiceState::Normal {
+            for index in 0..self.floors.len() {
+                if pressed & 1 << index == 0 {
+                    continue;
+                }
+                // a real passenger, hold off the demo calls
+                if let Some(demo) = &mut self.demo {
+                    demo.paused = true;
+                }
+                if self.latch_call(index) {
+                    return;
+                }
+            }
+            for index in 0..self.floors.len() {
+                if summons & 1 << index != 0 && self.summon_index(index) {
+                    return;
+                }
+            }
+            for index in 0..self.floors.len() {
+                // a long press takes back a call; held on an uncalled floor it still calls
+                if long_pressed & 1 << index == 0 {
+                    continue;
+                }
+                if self.floors[index].stop {
+                    self.cancel_call(index);
+                } else if self.latch_call(index) {
+                    return;
+                }
+            }
+        }
+        self.reconcile_level();
+        self.update_idle();
+        self.update_tone();
+        if self.service == ServiceState::Normal {
+            self.update_demo();
+        }
+        // while door is moving, do it
+        match self.door {
+            DoorState::Opening(progress) if self.vent.is_some_and(|vent| progress >= vent) => {
+                // vented, hold the doors part open; that counts as open time too
+                self.door_open_ticks = self.door_open_ticks.saturating_add(1);
+                if self.door_open_ticks >= self.max_door_open_ms / TICK_MS {
+                    self.door_forced_close = true;
+                    self.open_at_stop = false;
+                    self.vent = None;
+                    self.announce(self.language.phrases().stand_clear);
+                    self.set_door(DoorState::Closing(100 - progress));
+                }
+            }
+            DoorState::Opening(progress) => {
+                if self.open_announce_pending && progress >= self.announce_open_at {
+                    self.open_announce_pending = false;
+                    self.announce_floor(self.current_floor());
+                }
+                match progress {
+                    100.. => {
+                        self.set_door(DoorState::Open(0));
+                    }
+                    0 => {
+                        self.open_at_stop = false;
+                        if self.forced_target == Some(self.current_floor_index) {
+                            self.forced_target = None;
+                        }
+                        if core::mem::take(&mut self.arriving) {
+                            self.pending_arrival = Some(self.current_floor());
+                            // back-to-back stops during a peak don't need the full dwell
+                            let previous = self
+                                .last_service
+                                .and_then(|(floor, _)| self.try_floor_to_index(floor));
+                            self.short_dwell = self.traffic_mode != TrafficMode::Normal
+                                && previous.is_some_and(|previous| {
+                                    previous.abs_diff(self.current_floor_index) == 1
+                                });
+                        } else {
+                            self.short_dwell = false;
+                        }
+                        let number = self.current_floor();
+                        self.last_service = Some((number, self.ticks.wrapping_mul(TICK_MS)));
+                        if self.announce_open_at == 0 {
+                            self.announce_floor(number);
+                        } else {
+                            // wait until the doors are visibly moving
+                            self.open_announce_pending = true;
                         }
-                        self.set_door(DoorState::Opening(progress + 5)); // 2 secs to complete
+                        self.set_door(DoorState::Opening(self.opening_step(progress)));
+                        // 2 secs to complete
                     }
                     _ => {
-                        self.set_door(DoorState::Opening(progress + 5)); // 2 secs to complete
+                        self.set_door(DoorState::Opening(self.opening_step(progress)));
+                        // 2 secs to complete
                     }
                 }
             }
+            DoorState::Open(_)
+                if matches!(self.service, ServiceState::Recall { .. })
+                    && self.current_floor_index == self.lobby_index() =>
+            {
+                // recalled, hold the doors open at the lobby
+            }
+            DoorState::Open(_) if self.overloaded => {
+                // too heavy to leave, the dwell starts over once unloaded
+                self.set_door(DoorState::Open(0));
+            }
             DoorState::Open(progress) => {
-                if progress == 100 {
+                self.door_open_ticks = self.door_open_ticks.saturating_add(1);
+                let interval = self.reannounce_interval_ms / TICK_MS;
+                if interval > 0 && self.door_open_ticks.is_multiple_of(interval) {
+                    // for anyone who reached the landing after the arrival
+                    self.announce_direction(self.direction);
+                }
+                if self.door_open_ticks >= self.max_door_open_ms / TICK_MS {
+                    // held open for too long, close regardless of the open button
+                    self.door_forced_close = true;
+                    self.open_at_stop = false;
+                    self.announce(self.language.phrases().stand_clear);
+                    self.set_door(DoorState::Closing(0));
+                } else if self.holds.get() > 0 {
+                    // held, the timer restarts once the last hold is released
+                    self.set_door(DoorState::Open(0));
+                } else if progress >= 100 {
                     self.set_door(DoorState::Closing(0));
                 } else {
-                    self.set_door(DoorState::Open(progress + 2)); // 5 secs to complete
+                    self.set_door(DoorState::Open(progress + self.dwell_step()));
+                    // 5 secs to complete
                 }
             }
+            DoorState::Closing(progress)
+                if progress < 100
+                    && (self.overloaded || self.holds.get() > 0 && !self.door_forced_close) =>
+            {
+                // a hold or an overload arrived while closing, open back up
+                self.set_door(DoorState::Opening(100 - progress));
+            }
             DoorState::Closing(progress) => {
                 match progress {
-                    100 => {
+                    100.. => {
                         if self.floors[self.current_floor_index].stop {
                             let floor = &mut self.floors[self.current_floor_index];
                             floor.stop = false;
-                            floor.button.turn_off().unwrap();
+                            floor.fade_led();
                         }
+                        self.door_open_ticks = 0;
                         self.set_door(DoorState::Closed);
                     }
                     0 => {
-                        if let Some(callback) = &mut self.announce {
-                            callback(b"do'aga/shimarima'_su.\r");
+                        self.open_announce_pending = false;
+                        if self.announce_closing {
+                            self.announce(self.language.phrases().closing);
                         }
-                        self.set_door(DoorState::Closing(progress + 5)); // 2 secs to complete
+                        self.set_door(DoorState::Closing(progress + DOOR_MOVE_STEP));
+                        // 2 secs to complete
                     }
                     _ => {
-                        self.set_door(DoorState::Closing(progress + 5)); // 2 secs to complete
+                        self.set_door(DoorState::Closing(progress + DOOR_MOVE_STEP));
+                        // 2 secs to complete
+                    }
+                }
+            }
+            DoorState::Closed if self.leveling.is_some() => {
+                // stopped, levelling before the doors release
+                match self.leveling.unwrap() {
+                    0 | 1 => {
+                        self.leveling = None;
+                        self.set_door(DoorState::Opening(0));
                     }
+                    ticks => self.leveling = Some(ticks - 1),
                 }
             }
+            DoorState::Closed if self.travel_paused && self.is_moving() => {
+                // hold the car at its current progress until resume_travel
+            }
+            DoorState::Closed
+                if self.strict_door_safety && !self.door_settled && !self.is_moving() =>
+            {
+                // let the doors stay shut for a tick before committing to a direction
+                self.door_settled = true;
+            }
+            DoorState::Closed if self.service == ServiceState::Manual => {
+                self.update_manual();
+            }
             DoorState::Closed => {
+                let last = self.floors.len() - 1;
                 match self.direction {
+                    // moving past either end of the shaft, stop instead of indexing out of bounds
+                    Direction::Up(Some(_)) if self.current_floor_index == last => {
+                        self.emit(ElevatorEvent::ShaftEnd(self.current_floor()));
+                        self.set_direction(Direction::Idle);
+                    }
+                    Direction::Down(Some(_)) if self.current_floor_index == 0 => {
+                        self.emit(ElevatorEvent::ShaftEnd(self.current_floor()));
+                        self.set_direction(Direction::Idle);
+                    }
                     Direction::Up(value) => {
                         if let Some(progress) = value {
-                            if progress == 100 {
+                            if progress >= 100 {
                                 let index = self.current_floor_index + 1;
                                 self.set_current_floor_index(index);
-                                if self.floors[index].stop {
-                                    self.set_door(DoorState::Opening(0));
+                                self.announce_passing(index);
+                                if let Some(target) =
+                                    self.priority_target().filter(|&target| target != index)
+                                {
+                                    // pass other calls on the way to the priority target
+                                    if self.pass_acknowledge && self.floors[index].stop {
+                                        self.floors[index].pass_tick = Some(0);
+                                    }
+                                    self.set_direction(if target > index {
+                                        Direction::Up(Some(0))
+                                    } else {
+                                        Direction::Up(None)
+                                    });
+                                } else if self.floors[index].stop {
+                                    self.emit(ElevatorEvent::Arrived(self.current_floor()));
+                                    self.arriving = true;
+                                    // stop the car before the doors move
                                     self.set_direction(if index == self.floors.len() - 1 {
                                         if self.floors[..index]
                                             .iter()
@@ -237,13 +1910,25 @@ impl Elevator {
                                         }
                                     } else {
                                         Direction::Up(None)
-                                    })
-                                } else {
+                                    });
+                                    self.open_on_arrival();
+                                } else if self.park_index == Some(index) {
+                                    self.park_index = None;
+                                    self.set_direction(Direction::Idle);
+                                } else if self.floors[index + 1..].iter().any(|f| f.stop)
+                                    || self.park_index.is_some_and(|park| park > index)
+                                {
                                     self.set_direction(Direction::Up(Some(0)));
+                                } else {
+                                    // nothing left above, plan again
+                                    self.set_direction(Direction::Up(None));
                                 }
                             } else {
-                                self.set_direction(Direction::Up(Some(progress + 2)));
-                                // 5 secs to complete
+                                let next = self.current_floor_index + 1;
+                                let progress = progress
+                                    + self.leg_increment(self.up_travel_ms, progress, next);
+                                self.set_direction(Direction::Up(Some(progress.min(100))));
+                                // up_travel_ms to complete
                             }
                         } else {
                             self.goto_next_floor();
@@ -251,11 +1936,26 @@ impl Elevator {
                     }
                     Direction::Down(value) => {
                         if let Some(progress) = value {
-                            if progress == 100 {
+                            if progress >= 100 {
                                 let index = self.current_floor_index - 1;
                                 self.set_current_floor_index(index);
-                                if self.floors[index].stop {
-                                    self.set_door(DoorState::Opening(0));
+                                self.announce_passing(index);
+                                if let Some(target) =
+                                    self.priority_target().filter(|&target| target != index)
+                                {
+                                    // pass other calls on the way to the priority target
+                                    if self.pass_acknowledge && self.floors[index].stop {
+                                        self.floors[index].pass_tick = Some(0);
+                                    }
+                                    self.set_direction(if target < index {
+                                        Direction::Down(Some(0))
+                                    } else {
+                                        Direction::Down(None)
+                                    });
+                                } else if self.floors[index].stop {
+                                    self.emit(ElevatorEvent::Arrived(self.current_floor()));
+                                    self.arriving = true;
+                                    // stop the car before the doors move
                                     self.set_direction(if index == 0 {
                                         if self.floors[1..].iter().position(|f| f.stop).is_some() {
                                             Direction::Up(None)
@@ -264,31 +1964,105 @@ impl Elevator {
                                         }
                                     } else {
                                         Direction::Down(None)
-                                    })
-                                } else {
+                                    });
+                                    self.open_on_arrival();
+                                } else if self.park_index == Some(index) {
+                                    self.park_index = None;
+                                    self.set_direction(Direction::Idle);
+                                } else if self.floors[..index].iter().any(|f| f.stop)
+                                    || self.park_index.is_some_and(|park| park < index)
+                                {
                                     self.set_direction(Direction::Down(Some(0)));
+                                } else {
+                                    // nothing left below, plan again
+                                    self.set_direction(Direction::Down(None));
                                 }
                             } else {
-                                self.set_direction(Direction::Down(Some(progress + 2)));
-                                // 5 secs to complete
+                                let next = self.current_floor_index - 1;
+                                let progress = progress
+                                    + self.leg_increment(self.down_travel_ms, progress, next);
+                                self.set_direction(Direction::Down(Some(progress.min(100))));
+                                // down_travel_ms to complete
                             }
                         } else {
                             self.goto_next_floor();
                         }
                     }
                     Direction::Idle => {
-                        self.goto_next_floor();
+                        if self.open_at_stop {
+                            self.set_door(DoorState::Opening(0));
+                        } else {
+                            self.goto_next_floor();
+                        }
                     }
                 }
             }
         }
     }
 
+    // output phase: every LED write and callback that update only queued
+    pub fn dispatch(&mut self) {
+        for index in 0..self.floors.len() {
+            self.floors[index].flush_led();
+            if let Some(error) = self.floors[index].fault.take() {
+                let number = self.floors[index].number;
+                self.emit(ElevatorEvent::PinFault(Some(number), error));
+            }
+        }
+        for event in self.pending_events.drain(..) {
+            if let Some(callback) = &mut self.event {
+                callback(event);
+            }
+        }
+        if let Some(contrast) = self.pending_brightness.take() {
+            if let Some(callback) = &mut self.brightness {
+                callback(contrast);
+            }
+        }
+        if let Some(effect) = self.pending_sound.take() {
+            if let Some(sound) = &mut self.sound {
+                sound.play(effect);
+            }
+        }
+        if let Some(number) = self.pending_arrival.take() {
+            if let Some(callback) = &mut self.arrive {
+                callback(number);
+            }
+        }
+        let mut announcements = core::mem::take(&mut self.pending_announcements);
+        let mut ends = core::mem::take(&mut self.pending_announcement_ends);
+        let mut start = 0;
+        for &end in ends.iter() {
+            self.speak(&announcements[start..end]);
+            start = end;
+        }
+        announcements.clear();
+        ends.clear();
+        self.pending_announcements = announcements;
+        self.pending_announcement_ends = ends;
+        self.notify_idle();
+        if core::mem::take(&mut self.pending_repaint) {
+            if let Some(callback) = &mut self.repaint {
+                callback();
+            }
+            if let Some(callback) = self.repaint_fn {
+                callback();
+            }
+        }
+    }
+
     pub fn on_repaint<F>(&mut self, callback: F)
     where
         F: FnMut() + 'static,
     {
         self.repaint = Some(Box::new(callback));
+        self.repaint_fn = None;
+    }
+
+    // like on_repaint, but takes a plain function so nothing is allocated
+    pub fn set_repaint_fn(&mut self, callback: fn()) {
+        self.repaint = None;
+        self.repaint_fn = Some(callback);
     }
 
     pub fn on_announce<F>(&mut self, callback: F)
@@ -296,192 +2070,2757 @@ impl Elevator {
         F: FnMut(&[u8]) + 'static,
     {
         self.announce = Some(Box::new(callback));
+        self.announce_fn = None;
     }
 
-    pub fn floor_to_index(&self, floor: i8) -> usize {
-        self.floors.iter().position(|f| f.number == floor).unwrap()
+    // like on_announce, but takes a plain function so the framing isn't
+    // allocated; it may be called several times per announcement
+    pub fn set_announce_fn(&mut self, callback: fn(&[u8])) {
+        self.announce = None;
+        self.announce_fn = Some(callback);
     }
 
-    pub fn index_to_floor(&self, index: usize) -> i8 {
-        self.floors[index].number
+    // called once each time the car stops at a floor and starts opening its doors
+    pub fn on_arrive<F>(&mut self, callback: F)
+    where
+        F: FnMut(i8) + 'static,
+    {
+        self.arrive = Some(Box::new(callback));
     }
 
-    pub fn set_door_open(&mut self, value: bool) -> bool {
-        if value {
-            match self.door {
-                DoorState::Opening(_) => false,
-                DoorState::Open(_) => {
-                    self.set_door(DoorState::Open(0));
-                    true
-                }
+    // called once when the car comes to rest with its doors shut and no calls
+    pub fn on_idle<F>(&mut self, callback: F)
+    where
+        F: FnMut() + 'static,
+    {
+        self.idle_callback = Some(Box::new(callback));
+    }
+
+    // called once when a resting car gets something to do
+    pub fn on_busy<F>(&mut self, callback: F)
+    where
+        F: FnMut() + 'static,
+    {
+        self.busy_callback = Some(Box::new(callback));
+    }
+
+    pub fn on_event<F>(&mut self, callback: F)
+    where
+        F: FnMut(ElevatorEvent) + 'static,
+    {
+        self.event = Some(Box::new(callback));
+    }
+
+    // called with the display contrast (0..255) whenever it should change
+    pub fn on_brightness<F>(&mut self, callback: F)
+    where
+        F: FnMut(u8) + 'static,
+    {
+        self.brightness = Some(Box::new(callback));
+    }
+
+    // spoken once, on the first advance or an explicit announce_startup
+    pub fn set_startup_message(&mut self, message: &'static [u8]) {
+        self.startup_message = Some(message);
+    }
+
+    pub fn announce_startup(&mut self) {
+        if let Some(message) = self.startup_message.take() {
+            self.announce(message);
+        }
+    }
+
+    // speaks a phrase through the announce callback, framed like any announcement
+    pub fn say(&mut self, phrase: &[u8]) {
+        self.announce(phrase);
+    }
+
+    // pause between stopping at a floor and opening the doors, 0 opens right away
+    pub fn set_leveling_ms(&mut self, ms: u32) {
+        self.leveling_ms = ms;
+    }
+
+    // door openness (percent) at which the floor is announced, 0 announces as
+    // the doors start to open
+    pub fn set_announce_open_at(&mut self, percent: u8) {
+        self.announce_open_at = percent.min(100);
+    }
+
+    // mutes every announcement, the car otherwise runs as usual
+    pub fn set_quiet(&mut self, on: bool) {
+        self.quiet = on;
+    }
+
+    pub fn set_language(&mut self, language: Language) {
+        self.language = language;
+    }
+
+    pub fn set_announce_format(&mut self, format: AnnounceFormat) {
+        self.announce_format = format;
+    }
+
+    // None latches calls straight to a solid LED
+    pub fn set_ack_pattern(&mut self, pattern: Option<AckPattern>) {
+        self.ack_pattern = pattern;
+        if pattern.is_none() {
+            for floor in self.floors.iter_mut() {
+                if floor.ack_tick.take().is_some() {
+                    floor.set_led(floor.stop);
+                }
+            }
+        }
+    }
+
+    pub fn set_demo_traffic(&mut self, demo: Option<DemoTraffic>) {
+        self.demo = demo;
+    }
+
+    pub fn set_attract_mode(&mut self, enabled: bool) {
+        self.attract_mode = enabled;
+        if !enabled {
+            self.stop_attract();
+        }
+    }
+
+    // limit on the total time the doors stay open, including hold extensions
+    pub fn set_max_door_open_ms(&mut self, ms: u32) {
+        self.max_door_open_ms = ms;
+    }
+
+    pub fn set_sound<S>(&mut self, sound: S)
+    where
+        S: Sound + 'static,
+    {
+        self.sound = Some(Box::new(sound));
+    }
+
+    // plays a tone while travelling whose pitch rises and falls with the speed
+    pub fn set_travel_tone(&mut self, enabled: bool) {
+        self.travel_tone = enabled;
+    }
+
+    // speeds up gradually when leaving a stop and slows down before the next
+    pub fn set_acceleration_ramp(&mut self, enabled: bool) {
+        self.acceleration_ramp = enabled;
+    }
+
+    // on the way to a goto target or priority call, ease off and flash the LED
+    // of each pending call passed, to show it's been seen and will be served
+    pub fn set_pass_acknowledge(&mut self, enabled: bool) {
+        self.pass_acknowledge = enabled;
+    }
+
+    // also name every floor passed on the way, off by default as it's chatty
+    pub fn set_announce_passing(&mut self, enabled: bool) {
+        self.set_announce_every(enabled as u8);
+    }
+
+    // on long runs, only name every `floors`th floor passed since the last stop
+    pub fn set_announce_every(&mut self, floors: u8) {
+        self.announce_every = floors;
+    }
+
+    // the "doors closing" phrase on its own, floors and directions are still named
+    pub fn set_announce_closing(&mut self, on: bool) {
+        self.announce_closing = on;
+    }
+
+    // while the doors stay open, say the direction again every `ms`, 0 disables it
+    pub fn set_reannounce_interval_ms(&mut self, ms: u32) {
+        self.reannounce_interval_ms = ms;
+    }
+
+    // wait a tick after the doors close before departing, so a late reopen can't race it
+    pub fn set_strict_door_safety(&mut self, enabled: bool) {
+        self.strict_door_safety = enabled;
+    }
+
+    // shows `arrow` while the car is idle; a real direction still wins
+    pub fn set_idle_arrow(&mut self, arrow: Option<Direction>) {
+        // a standing arrow, so it doesn't animate as if travelling
+        self.idle_arrow = arrow.map(|arrow| match arrow {
+            Direction::Up(_) => Direction::Up(None),
+            Direction::Down(_) => Direction::Down(None),
+            Direction::Idle => Direction::Idle,
+        });
+        self.request_repaint();
+    }
+
+    fn shown_direction(&self) -> Direction {
+        match self.direction {
+            Direction::Idle => self.idle_arrow.unwrap_or(Direction::Idle),
+            direction => direction,
+        }
+    }
+
+    pub fn set_door_style(&mut self, style: DoorStyle) {
+        self.door_style = style;
+        self.request_repaint();
+    }
+
+    pub fn set_display_mode(&mut self, mode: DisplayMode) {
+        self.display_mode = mode;
+        self.request_repaint();
+    }
+
+    // blinks the top-left pixel on every advance to show the main loop is running
+    pub fn set_show_heartbeat(&mut self, enabled: bool) {
+        self.show_heartbeat = enabled;
+    }
+
+    // doubles travel speed, not door timing, until the car next goes idle
+    pub fn turbo(&mut self, on: bool) {
+        self.turbo = on;
+    }
+
+    // time to travel one floor in each direction
+    pub fn set_travel_ms(&mut self, up_ms: u32, down_ms: u32) {
+        self.up_travel_ms = up_ms;
+        self.down_travel_ms = down_ms;
+    }
+
+    pub fn start_self_test(&mut self) {
+        self.self_test = Some(0);
+    }
+
+    pub fn set_scheduler(&mut self, scheduler: Scheduler) {
+        self.scheduler = scheduler;
+    }
+
+    // once a call has waited `ms`, the car heads for it next whatever the
+    // scheduler prefers; 0 turns the guard off
+    pub fn set_max_wait_ms(&mut self, ms: u32) {
+        self.max_wait_ms = ms;
+    }
+
+    // sets the wall clock, which then runs off the tick count
+    pub fn set_time(&mut self, hour: u8, minute: u8) {
+        let minute_of_day = (hour as u16 % 24) * 60 + minute as u16 % 60;
+        self.clock = Some((minute_of_day, self.ticks));
+    }
+
+    // minutes since midnight, None until set_time
+    pub fn time_of_day(&self) -> Option<u16> {
+        let (minute_of_day, set_at) = self.clock?;
+        let elapsed = self.ticks.wrapping_sub(set_at) / (60_000 / TICK_MS);
+        Some(((minute_of_day as u32 + elapsed) % (24 * 60)) as u16)
+    }
+
+    // idle cars park at `floor` between the two minutes of the day; the first
+    // matching window wins, windows may wrap past midnight
+    pub fn set_parking_schedule(&mut self, windows: &[(u16, u16, i8)]) {
+        self.parking_schedule.clear();
+        for window in windows.iter().take(PARKING_WINDOWS) {
+            let _ = self.parking_schedule.push(*window);
+        }
+    }
+
+    pub fn set_traffic_mode(&mut self, mode: TrafficMode) {
+        self.traffic_mode = mode;
+    }
+
+    // during up or down peak, a stop one floor on from the last keeps its
+    // doors open for `percent` of the usual dwell; 100 leaves it unchanged
+    pub fn set_adjacent_dwell_percent(&mut self, percent: u8) {
+        self.adjacent_dwell_percent = percent;
+    }
+
+    // recall to the lobby if feed_comm isn't called within `ms`, None disables it
+    pub fn set_comm_watchdog_ms(&mut self, ms: Option<u32>) {
+        self.comm_watchdog_ms = ms;
+        self.comm_ticks = 0;
+    }
+
+    // fire-recall style: drop every call and hold the doors open at the lobby
+    pub fn recall(&mut self) {
+        match self.service {
+            // already on the way, but now it takes a release to end it
+            ServiceState::Recall { .. } => {
+                self.service = ServiceState::Recall { comm_loss: false };
+            }
+            _ => self.start_recall(false),
+        }
+    }
+
+    // finishes the current door cycle, parks at the lobby with the doors
+    // closed, then turns every LED off and ignores all input
+    pub fn shutdown(&mut self) {
+        if matches!(self.service, ServiceState::ShuttingDown | ServiceState::Off) {
+            return;
+        }
+        self.park_index = None;
+        self.forced_target = None;
+        self.vent = None;
+        self.demo = None;
+        self.stop_attract();
+        for floor in self.floors.iter_mut() {
+            floor.stop = false;
+            floor.set_led(false);
+        }
+        let lobby = self.lobby_index();
+        if self.current_floor_index != lobby {
+            self.latch_call(lobby);
+        }
+        self.service = ServiceState::ShuttingDown;
+        self.request_repaint();
+    }
+
+    pub fn release_recall(&mut self) {
+        if matches!(self.service, ServiceState::Recall { .. }) {
+            self.service = ServiceState::Normal;
+        }
+    }
+
+    // recalled and standing at the lobby with the doors open
+    pub fn is_recalled(&self) -> bool {
+        matches!(self.service, ServiceState::Recall { .. })
+            && self.current_floor_index == self.lobby_index()
+            && matches!(self.door, DoorState::Open(_))
+    }
+
+    pub fn feed_comm(&mut self) {
+        self.comm_ticks = 0;
+        if self.service == (ServiceState::Recall { comm_loss: true }) {
+            self.service = ServiceState::Normal;
+        }
+    }
+
+    // times a level sensor disagreed with the modelled position
+    pub fn level_mismatches(&self) -> u32 {
+        self.level_mismatches
+    }
+
+    // hands the car to an operator; pending calls are dropped
+    pub fn set_manual_mode(&mut self, enabled: bool) {
+        if matches!(self.service, ServiceState::ShuttingDown | ServiceState::Off) {
+            return;
+        }
+        if enabled {
+            self.service = ServiceState::Manual;
+            self.park_index = None;
+            self.forced_target = None;
+            for floor in self.floors.iter_mut() {
+                floor.stop = false;
+                floor.set_led(false);
+            }
+            self.request_repaint();
+        } else if self.service == ServiceState::Manual {
+            self.service = ServiceState::Normal;
+            self.manual_up = false;
+            self.manual_down = false;
+        }
+    }
+
+    pub fn manual_up(&mut self, held: bool) {
+        self.manual_up = held;
+    }
+
+    pub fn manual_down(&mut self, held: bool) {
+        self.manual_down = held;
+    }
+
+    // the last EVENT_LOG_SIZE events, oldest first
+    pub fn recent_events(&self) -> impl Iterator<Item = &ElevatorEvent> {
+        self.events.oldest_ordered()
+    }
+
+    pub fn service_state(&self) -> ServiceState {
+        self.service
+    }
+
+    pub fn pause_travel(&mut self) {
+        if !self.travel_paused && self.is_moving() {
+            self.emit(ElevatorEvent::Emergency);
+        }
+        self.travel_paused = true;
+    }
+
+    // from a load sensor; while set the doors won't close and the car stays put
+    pub fn set_overload(&mut self, overloaded: bool) {
+        if overloaded && !self.overloaded {
+            self.emit(ElevatorEvent::Overload);
+        }
+        self.overloaded = overloaded;
+    }
+
+    pub fn resume_travel(&mut self) {
+        self.travel_paused = false;
+    }
+
+    // like call_floor, but the scheduler serves higher levels first and out of
+    // LOOK order; the call's LED blinks while it waits
+    pub fn call_floor_priority(&mut self, floor: i8, level: u8) -> bool {
+        let Some(index) = self.try_floor_to_index(floor) else {
+            return false;
+        };
+        self.latch_call(index);
+        let floor = &mut self.floors[index];
+        if floor.stop {
+            floor.priority = floor.priority.max(level);
+        }
+        true
+    }
+
+    pub fn call_floor(&mut self, floor: i8) -> bool {
+        match self.try_floor_to_index(floor) {
+            Some(index) => {
+                self.latch_call(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // calls the car from the landing at `floor`
+    pub fn summon(&mut self, floor: i8) -> bool {
+        match self.try_floor_to_index(floor) {
+            Some(index) => {
+                self.summon_index(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // pending and placed from the landing, not the cabin
+    pub fn is_summoned(&self, floor: i8) -> bool {
+        match self.try_floor_to_index(floor) {
+            Some(index) => self.floors[index].stop && self.floors[index].summoned,
+            None => false,
+        }
+    }
+
+    // drives the car straight to `floor`, ahead of the other calls, which
+    // are kept and served afterwards
+    pub fn goto(&mut self, floor: i8) -> bool {
+        let Some(index) = self.try_floor_to_index(floor) else {
+            return false;
+        };
+        self.forced_target = Some(index);
+        self.latch_call(index);
+        if !self.floors[index].stop {
+            // the call was refused
+            self.forced_target = None;
+        }
+        true
+    }
+
+    // moves the selection cursor, starting from the current floor
+    pub fn move_highlight(&mut self, delta: i8) {
+        let last = self.floors.len() as i32 - 1;
+        let mut index = self.highlighted.unwrap_or(self.current_floor_index) as i32;
+        for _ in 0..delta.unsigned_abs() {
+            // skip over floors that can't be called
+            let step = delta.signum() as i32;
+            let mut next = index + step;
+            while (0..=last).contains(&next) && !self.floors[next as usize].stoppable() {
+                next += step;
+            }
+            if (0..=last).contains(&next) {
+                index = next;
+            }
+        }
+        let index = index as usize;
+        if self.highlighted != Some(index) {
+            self.highlighted = Some(index);
+            self.request_repaint();
+        }
+    }
+
+    pub fn confirm_selection(&mut self) -> bool {
+        match self.highlighted.take() {
+            Some(index) => {
+                self.call_floor(self.floors[index].number);
+                self.request_repaint();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Panics if `floor` is not one of the configured floors. Use
+    /// `try_floor_to_index` for floor numbers coming from outside.
+    pub fn floor_to_index(&self, floor: i8) -> usize {
+        self.try_floor_to_index(floor).unwrap()
+    }
+
+    pub fn try_floor_to_index(&self, floor: i8) -> Option<usize> {
+        self.floors.iter().position(|f| f.number == floor)
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            floor: self.current_floor(),
+            direction: self.direction,
+            door: self.door,
+            calls: self.call_mask() as u8,
+        }
+    }
+
+    // puts the car back into a snapshot's state; out-of-range progress, unknown
+    // floors and travel with open doors are clamped to the nearest valid state
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        let mut clamped = false;
+        let mut clamp = |progress: u8| {
+            clamped |= progress > 100;
+            progress.min(100)
+        };
+        let mut direction = match snapshot.direction {
+            Direction::Up(progress) => Direction::Up(progress.map(&mut clamp)),
+            Direction::Down(progress) => Direction::Down(progress.map(&mut clamp)),
+            Direction::Idle => Direction::Idle,
+        };
+        let door = match snapshot.door {
+            DoorState::Opening(progress) => DoorState::Opening(clamp(progress)),
+            DoorState::Open(progress) => DoorState::Open(clamp(progress)),
+            DoorState::Closing(progress) => DoorState::Closing(clamp(progress)),
+            DoorState::Closed => DoorState::Closed,
+        };
+        let index = match self.try_floor_to_index(snapshot.floor) {
+            Some(index) => index,
+            None => {
+                clamped = true;
+                self.current_floor_index
+            }
+        };
+        let moving = matches!(direction, Direction::Up(Some(_)) | Direction::Down(Some(_)));
+        let off_the_end = match direction {
+            Direction::Up(Some(_)) => index == self.floors.len() - 1,
+            Direction::Down(Some(_)) => index == 0,
+            _ => false,
+        };
+        if off_the_end || (moving && door != DoorState::Closed) {
+            clamped = true;
+            direction = Direction::Idle;
+        }
+
+        // bypass set_direction/set_door, a restore is not a departure or door cycle
+        self.direction = direction;
+        self.door = door;
+        self.set_current_floor_index(index);
+        for (i, floor) in self.floors.iter_mut().enumerate() {
+            floor.stop = snapshot.calls & 1 << i != 0 && floor.stoppable();
+            floor.set_led(floor.stop);
+        }
+        self.request_repaint();
+        if clamped {
+            self.emit(ElevatorEvent::StateClamped);
+        }
+    }
+
+    // bit i set while floor index i is called
+    pub fn call_mask(&self) -> u16 {
+        self.floors
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.stop)
+            .fold(0, |calls, (i, _)| calls | 1 << i)
+    }
+
+    // latches the set bits and cancels the clear ones in one go; bits past
+    // the top floor are ignored
+    pub fn set_call_mask(&mut self, mask: u16) {
+        for index in 0..self.floors.len() {
+            let wanted = mask & 1 << index != 0;
+            if wanted {
+                self.latch_call(index);
+            } else {
+                self.cancel_call(index);
+            }
+        }
+    }
+
+    // compares against a snapshot the caller took at its last poll, so any number
+    // of changes in between come back as one ChangeSet; travel and door progress
+    // alone don't count as a change
+    pub fn poll_changes(&self, last: &Snapshot) -> ChangeSet {
+        let now = self.snapshot();
+        ChangeSet {
+            floor: now.floor != last.floor,
+            direction: discriminant(&now.direction) != discriminant(&last.direction),
+            door: discriminant(&now.door) != discriminant(&last.door),
+            calls: now.calls != last.calls,
+        }
+    }
+
+    // scripts the press state of a floor button; false if it can't be overridden
+    pub fn force_button(&mut self, floor: i8, pressed: Option<bool>) -> bool {
+        match self.try_floor_to_index(floor) {
+            Some(index) => self.floors[index].button.set_forced(pressed).is_some(),
+            None => false,
+        }
+    }
+
+    // how long the call at `floor` has been waiting, None if there is none
+    pub fn call_age_ms(&self, floor: i8) -> Option<u32> {
+        let floor = &self.floors[self.try_floor_to_index(floor)?];
+        floor.stop.then(|| {
+            self.ticks
+                .wrapping_sub(floor.latched_at)
+                .wrapping_mul(TICK_MS)
+        })
+    }
+
+    pub fn is_called(&self, floor: i8) -> bool {
+        match self.try_floor_to_index(floor) {
+            Some(index) => self.floors[index].stop,
+            None => false,
+        }
+    }
+
+    pub fn index_to_floor(&self, index: usize) -> i8 {
+        self.floors[index].number
+    }
+
+    // floor number and uptime in ms of the last arrival with the doors opening
+    pub fn last_service(&self) -> Option<(i8, u32)> {
+        self.last_service
+    }
+
+    pub fn needs_redraw(&self) -> bool {
+        self.dirty.get()
+    }
+
+    pub fn current_floor(&self) -> i8 {
+        self.floors[self.current_floor_index].number
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn door_state(&self) -> DoorState {
+        self.door
+    }
+
+    // estimated time left in the current door or travel phase, None when idle or
+    // held at a vent
+    pub fn phase_remaining_ms(&self) -> Option<u32> {
+        match self.door {
+            // held part open until told otherwise, there's no end to estimate
+            DoorState::Opening(progress) if self.vent.is_some_and(|vent| progress >= vent) => None,
+            DoorState::Opening(progress) if self.vent.is_some() => {
+                let left = self.vent.unwrap() - progress;
+                Some(left.div_ceil(DOOR_MOVE_STEP) as u32 * TICK_MS)
+            }
+            DoorState::Opening(progress) | DoorState::Closing(progress) => {
+                Some(remaining_ms(progress, DOOR_MOVE_STEP))
+            }
+            DoorState::Open(progress) => {
+                let forced = (self.max_door_open_ms / TICK_MS)
+                    .saturating_sub(self.door_open_ticks)
+                    .saturating_mul(TICK_MS);
+                Some(remaining_ms(progress, self.dwell_step()).min(forced))
+            }
+            DoorState::Closed => match self.direction {
+                Direction::Up(Some(progress)) => Some(remaining_ms(
+                    progress,
+                    self.travel_increment(self.up_travel_ms),
+                )),
+                Direction::Down(Some(progress)) => Some(remaining_ms(
+                    progress,
+                    self.travel_increment(self.down_travel_ms),
+                )),
+                _ => None,
+            },
+        }
+    }
+
+    // where the car should be in the shaft right now, interpolated during travel
+    pub fn target_position_mm(&self) -> Option<i32> {
+        let heights = self.floor_heights_mm?;
+        let index = self.current_floor_index;
+        let (next, progress) = match self.direction {
+            Direction::Up(Some(progress)) => (index + 1, progress),
+            Direction::Down(Some(progress)) => (index.wrapping_sub(1), progress),
+            _ => (index, 0),
+        };
+        let from = heights[index];
+        let to = heights.get(next).copied().unwrap_or(from);
+        Some(from + (to - from) * progress as i32 / 100)
+    }
+
+    // estimated time until the car opens its doors at `floor`, following
+    // service_order; None if the floor isn't part of the current plan
+    pub fn eta_to(&self, floor: i8) -> Option<u32> {
+        let target = self.try_floor_to_index(floor)?;
+        // each phase also spends the tick that hands over to the next one
+        let door_ms = remaining_ms(0, DOOR_MOVE_STEP) + TICK_MS;
+        let hold_ms = remaining_ms(0, DOOR_OPEN_STEP) + TICK_MS;
+        let up_ms = remaining_ms(0, self.travel_increment(self.up_travel_ms)) + TICK_MS;
+        let down_ms = remaining_ms(0, self.travel_increment(self.down_travel_ms)) + TICK_MS;
+        let leveling_ms = self.leveling_ms / TICK_MS * TICK_MS;
+        // a stop on the way: leveling, a full door cycle, then a tick to set off
+        let stop_ms = leveling_ms + door_ms * 2 + hold_ms + TICK_MS;
+        let mut position = self.current_floor_index;
+        let serving = self.door != DoorState::Closed;
+        let mut eta = match self.door {
+            _ if serving && target == position => return Some(0),
+            DoorState::Opening(progress) => {
+                remaining_ms(progress, DOOR_MOVE_STEP) + hold_ms + door_ms + TICK_MS * 2
+            }
+            DoorState::Open(_) => self.phase_remaining_ms()? + door_ms + TICK_MS * 2,
+            DoorState::Closing(_) => self.phase_remaining_ms()? + TICK_MS * 2,
+            DoorState::Closed => match self.direction {
+                Direction::Up(Some(_)) => {
+                    position += 1;
+                    self.phase_remaining_ms()? + TICK_MS
+                }
+                Direction::Down(Some(_)) => {
+                    position -= 1;
+                    self.phase_remaining_ms()? + TICK_MS
+                }
+                _ => 0,
+            },
+        };
+        for stop in self.service_order() {
+            let index = self.floor_to_index(stop);
+            if serving && index == self.current_floor_index {
+                // already accounted for above
+                continue;
+            }
+            eta += if index > position {
+                (index - position) as u32 * up_ms
+            } else {
+                (position - index) as u32 * down_ms
+            };
+            if index == target {
+                return Some(eta + leveling_ms);
+            }
+            eta += stop_ms;
+            position = index;
+        }
+        None
+    }
+
+    // floors the car will stop at, in order, if no new calls come in
+    pub fn service_order(&self) -> heapless::Vec<i8, 8> {
+        let index = self.current_floor_index;
+        let len = self.floors.len();
+        let direction = match self.direction {
+            Direction::Idle => self.idle_direction(),
+            direction => direction,
+        };
+        // a moving car has already left its current floor behind
+        let ahead = if self.is_moving() { 1 } else { 0 };
+        let mut order = heapless::Vec::new();
+        let mut push = |i: usize| {
+            if self.floors[i].stop {
+                let _ = order.push(self.floors[i].number);
+            }
+        };
+        match direction {
+            Direction::Up(_) => {
+                (index + ahead..len).for_each(&mut push);
+                (0..index + ahead).rev().for_each(&mut push);
+            }
+            Direction::Down(_) => {
+                (0..(index + 1).saturating_sub(ahead))
+                    .rev()
+                    .for_each(&mut push);
+                ((index + 1).saturating_sub(ahead)..len).for_each(&mut push);
+            }
+            Direction::Idle => {}
+        }
+        order
+    }
+
+    // whether the car stops at `floor` before it next reverses; unlike
+    // is_called, a call left for the return pass doesn't count
+    pub fn will_stop_here(&self, floor: i8) -> bool {
+        let Some(target) = self.try_floor_to_index(floor) else {
+            return false;
+        };
+        if !self.floors[target].stop {
+            return false;
+        }
+        let index = self.current_floor_index;
+        let direction = match self.direction {
+            Direction::Idle => self.idle_direction(),
+            direction => direction,
+        };
+        // same split of the floors as service_order
+        let ahead = if self.is_moving() { 1 } else { 0 };
+        match direction {
+            Direction::Up(_) => target >= index + ahead,
+            Direction::Down(_) => target < (index + 1).saturating_sub(ahead),
+            Direction::Idle => false,
+        }
+    }
+
+    fn big_label(&self) -> &'static str {
+        let floor = &self.floors[self.current_floor_index];
+        floor.big_label.unwrap_or(floor.label)
+    }
+
+    pub fn floor_count(&self) -> usize {
+        self.floors.len()
+    }
+
+    pub fn min_floor(&self) -> i8 {
+        self.floors.iter().map(|f| f.number).min().unwrap()
+    }
+
+    pub fn max_floor(&self) -> i8 {
+        self.floors.iter().map(|f| f.number).max().unwrap()
+    }
+
+    // opens the doors now if possible, otherwise as soon as the car is stopped
+    pub fn request_open_at_stop(&mut self) {
+        if self.door_forced_close {
+            // the doors were held too long, queueing would just reopen them
+            return;
+        }
+        if !matches!(self.door, DoorState::Opening(_)) && !self.set_door_open(true) {
+            self.open_at_stop = true;
+        }
+    }
+
+    // opens the doors to `percent` and holds them there until set_door_open;
+    // the car can't move meanwhile as the doors aren't closed
+    pub fn set_door_vent(&mut self, percent: u8) -> bool {
+        let percent = percent.clamp(DOOR_MOVE_STEP, 100);
+        match self.door {
+            DoorState::Closed if self.direction == Direction::Idle => {
+                self.set_door(DoorState::Opening(0));
+            }
+            DoorState::Opening(_) => {}
+            _ => return false,
+        }
+        self.vent = (percent < 100).then_some(percent);
+        true
+    }
+
+    // the doors stay open while any returned token is alive, so several
+    // sources (open button, hall hold, ...) can hold them independently
+    pub fn acquire_hold(&self) -> HoldToken {
+        self.holds.set(self.holds.get() + 1);
+        HoldToken(self.holds.clone())
+    }
+
+    // takes the open and close inputs together; open wins when both are
+    // asserted, so conflicting inputs never close the doors on anyone
+    pub fn door_command(&mut self, open: bool, close: bool) -> bool {
+        if !open {
+            // let go at last, the next press is a real one
+            self.door_forced_close = false;
+        }
+        if open {
+            self.set_door_open(true)
+        } else if close {
+            self.set_door_open(false)
+        } else {
+            false
+        }
+    }
+
+    pub fn set_door_open(&mut self, value: bool) -> bool {
+        if self.service == ServiceState::Off {
+            return false;
+        }
+        if value && self.door_forced_close {
+            return false;
+        }
+        if value {
+            if self.vent.take().is_some() {
+                // carry on to fully open
+                return true;
+            }
+            match self.door {
+                DoorState::Opening(_) => false,
+                DoorState::Open(_) => {
+                    self.set_door(DoorState::Open(0));
+                    true
+                }
                 DoorState::Closing(progress) => {
                     self.set_door(DoorState::Opening(100 - progress));
                     true
                 }
-                DoorState::Closed => {
-                    if self.direction == Direction::Idle {
-                        self.set_door(DoorState::Opening(0));
-                        true
-                    } else {
-                        false
+                DoorState::Closed => match self.direction {
+                    _ if !self.is_moving() => {
+                        self.set_door(DoorState::Opening(0));
+                        true
+                    }
+                    // only just departed, the car is still level with the floor
+                    Direction::Up(Some(progress)) if progress < DEPARTURE_GRACE => {
+                        self.set_direction(Direction::Up(None));
+                        self.set_door(DoorState::Opening(0));
+                        true
+                    }
+                    Direction::Down(Some(progress)) if progress < DEPARTURE_GRACE => {
+                        self.set_direction(Direction::Down(None));
+                        self.set_door(DoorState::Opening(0));
+                        true
+                    }
+                    _ => false,
+                },
+            }
+        } else {
+            match self.door {
+                DoorState::Open(_) if self.holds.get() > 0 || self.overloaded => false,
+                DoorState::Open(_) => {
+                    self.set_door(DoorState::Closing(0));
+                    true
+                }
+                DoorState::Opening(progress) if self.vent.take().is_some() => {
+                    self.set_door(DoorState::Closing(100 - progress));
+                    true
+                }
+                _ => false,
+            }
+        }
+    }
+}
+
+impl Elevator {
+    // fits the target height, so it works on 128x32 as well as 128x64
+    fn draw_schematic<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let height = target.bounding_box().size.height as i32;
+        let last = self.floors.len() as i32 - 1;
+        // floor 0 at the bottom, 3px of room above and below for the car
+        let floor_y = |position: i32| 3 + (height - 7) * (last * 100 - position) / (last * 100);
+        let spacing = (height - 7) / last;
+        let on = PrimitiveStyleBuilder::new()
+            .fill_color(BinaryColor::On)
+            .build();
+        let outline = PrimitiveStyleBuilder::new()
+            .stroke_color(BinaryColor::On)
+            .stroke_width(1)
+            .build();
+        let text_style = MonoTextStyleBuilder::new()
+            .font(&FONT_5X8)
+            .text_color(BinaryColor::On)
+            .build();
+
+        Rectangle::new(Point::new(10, 0), Size::new(1, height as u32)).draw_styled(&on, target)?;
+        Rectangle::new(Point::new(20, 0), Size::new(1, height as u32)).draw_styled(&on, target)?;
+        for (i, floor) in self.floors.iter().enumerate() {
+            let y = floor_y(i as i32 * 100);
+            Rectangle::new(Point::new(21, y), Size::new(3, 1)).draw_styled(&on, target)?;
+            if floor.stop {
+                Rectangle::new(Point::new(4, y - 1), Size::new(3, 3)).draw_styled(&on, target)?;
+            }
+            // labels only fit when the floors are at least a line apart
+            if spacing >= 8 {
+                Text::new(floor.label, Point::new(27, y + 3), text_style).draw(target)?;
+            }
+        }
+
+        let position = self.current_floor_index as i32 * 100
+            + match self.direction {
+                Direction::Up(Some(progress)) => progress as i32,
+                Direction::Down(Some(progress)) => -(progress as i32),
+                _ => 0,
+            };
+        let car = Rectangle::new(Point::new(12, floor_y(position) - 3), Size::new(7, 7));
+        // hollow while the doors are open
+        if self.door == DoorState::Closed {
+            car.draw_styled(&on, target)?;
+        } else {
+            car.draw_styled(&outline, target)?;
+        }
+
+        let label = self.big_label();
+        #[cfg(feature = "big-font")]
+        let font = &FONT_10X20;
+        #[cfg(not(feature = "big-font"))]
+        let font = &FONT_5X8;
+        Text::with_alignment(
+            label,
+            Point::new(100, height / 2 + font.character_size.height as i32 / 2 - 2),
+            MonoTextStyleBuilder::new()
+                .font(font)
+                .text_color(BinaryColor::On)
+                .build(),
+            Alignment::Center,
+        )
+        .draw(target)?;
+        Ok(())
+    }
+}
+
+impl embedded_graphics::Drawable for Elevator {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        self.dirty.set(false);
+        if self.service == ServiceState::Off {
+            let style = MonoTextStyleBuilder::new()
+                .font(&FONT_5X8)
+                .text_color(BinaryColor::On)
+                .build();
+            Text::with_alignment("POWERED OFF", Point::new(64, 34), style, Alignment::Center)
+                .draw(target)?;
+            return Ok(());
+        }
+        if self.display_mode == DisplayMode::Schematic {
+            return self.draw_schematic(target);
+        }
+        let door_openess = match self.door {
+            DoorState::Opening(progress) => progress,
+            DoorState::Open(_) => 100,
+            DoorState::Closing(progress) => 100 - progress,
+            DoorState::Closed => 0,
+        };
+        let margin = 20u32;
+        let door_width = (100 - door_openess) as u32 * (128 - margin * 2) / 100;
+        // the wall plus whatever leaf is showing on each side
+        let (left, right) = match self.door_style {
+            DoorStyle::CenterOpening => (door_width + margin, door_width + margin),
+            DoorStyle::SideOpeningLeft => (door_width + margin, margin),
+            DoorStyle::SideOpeningRight => (margin, door_width + margin),
+        };
+        let door_style = PrimitiveStyleBuilder::new()
+            .fill_color(BinaryColor::On)
+            .build();
+        Rectangle::new(
+            Point::new(0, 0),
+            embedded_graphics::geometry::Size::new(left, 64),
+        )
+        .draw_styled(&door_style, target)?;
+        Rectangle::new(
+            Point::new(128 - right as i32, 0),
+            embedded_graphics::geometry::Size::new(right, 64),
+        )
+        .draw_styled(&door_style, target)?;
+
+        let background_style_highlighted = PrimitiveStyleBuilder::new()
+            .fill_color(BinaryColor::Off)
+            .build();
+
+        let text_style_highlighted = MonoTextStyleBuilder::new()
+            .font(&FONT_5X8)
+            .text_color(BinaryColor::On)
+            .build();
+        let text_style = MonoTextStyleBuilder::new()
+            .font(&FONT_5X8)
+            .text_color(BinaryColor::Off)
+            .build();
+
+        // 128x64
+        for i in 0..self.floors.len() {
+            let floor = &self.floors[i];
+            let y = 56 - i as i32 * 8;
+            // labels are right aligned so they share a right edge whatever their length
+            let anchor = Point::new(128 - 3, y + 6);
+            if floor.out_of_service {
+                // a cross over the label of a floor that's closed for now
+                let corner = Point::new(128 - 12, y + 1);
+                let stroke = PrimitiveStyle::with_stroke(BinaryColor::Off, 1);
+                Line::new(corner, corner + Point::new(8, 6)).draw_styled(&stroke, target)?;
+                Line::new(corner + Point::new(0, 6), corner + Point::new(8, 0))
+                    .draw_styled(&stroke, target)?;
+            } else if !floor.stoppable() {
+                // a faint dotted row for floors that can't be called
+                for x in (128 - 12..128 - 2).step_by(2) {
+                    Pixel(Point::new(x, y + 4), BinaryColor::Off).draw(target)?;
+                }
+            } else if floor.stop {
+                Rectangle::new(Point::new(128 - 14, y), Size::new(14, 8))
+                    .draw_styled(&background_style_highlighted, target)?;
+                Text::with_alignment(
+                    floor.label,
+                    anchor,
+                    text_style_highlighted,
+                    Alignment::Right,
+                )
+                .draw(target)?;
+            } else {
+                Text::with_alignment(floor.label, anchor, text_style, Alignment::Right)
+                    .draw(target)?;
+            }
+            if floor.stop && floor.summoned {
+                // a dot beside landing calls, cabin calls only get the box
+                Rectangle::new(Point::new(128 - 19, y + 3), Size::new(3, 3))
+                    .draw_styled(&PrimitiveStyle::with_fill(BinaryColor::Off), target)?;
+            }
+            if self.highlighted == Some(i) {
+                Rectangle::new(Point::new(128 - 14, y), Size::new(14, 8)).draw_styled(
+                    &PrimitiveStyleBuilder::new()
+                        .stroke_color(if floor.stop {
+                            BinaryColor::On
+                        } else {
+                            BinaryColor::Off
+                        })
+                        .stroke_width(1)
+                        .build(),
+                    target,
+                )?;
+            }
+            if i == self.current_floor_index {
+                let y = match self.direction {
+                    Direction::Up(value) => {
+                        if let Some(progress) = value {
+                            y - progress as i32 * 8 / 100
+                        } else {
+                            y
+                        }
+                    }
+                    Direction::Down(value) => {
+                        if let Some(progress) = value {
+                            y + progress as i32 * 8 / 100
+                        } else {
+                            y
+                        }
                     }
+                    Direction::Idle => y,
+                };
+                Triangle::new(
+                    Point::new(128 - margin as i32 + 1, y + 2),
+                    Point::new(128 - margin as i32 + 1, y + 6),
+                    Point::new(128 - margin as i32 + 4, y + 4),
+                )
+                .draw_styled(&background_style_highlighted, target)?;
+            }
+        }
+
+        match self.shown_direction() {
+            Direction::Up(value) => {
+                let dy = if let Some(progress) = value {
+                    progress as i32 / 5 % 10
+                } else {
+                    0
+                };
+                let y = 10 - dy;
+                let height = 13;
+                let width = 7;
+                Triangle::new(
+                    Point::new(margin as i32 / 2 - 1, y),
+                    Point::new(margin as i32 / 2 - 1 - width, y + height),
+                    Point::new(margin as i32 / 2 - 1 + width, y + height),
+                )
+                .draw_styled(
+                    &PrimitiveStyleBuilder::new()
+                        .fill_color(BinaryColor::Off)
+                        .build(),
+                    target,
+                )?;
+            }
+            Direction::Down(value) => {
+                let dy = if let Some(progress) = value {
+                    progress as i32 / 5 % 10
+                } else {
+                    0
+                };
+                let y = 41 + dy;
+                let height = 13;
+                let width = 7;
+                Triangle::new(
+                    Point::new(margin as i32 / 2 - 1, y + height),
+                    Point::new(margin as i32 / 2 - 1 - width, y),
+                    Point::new(margin as i32 / 2 - 1 + width, y),
+                )
+                .draw_styled(
+                    &PrimitiveStyleBuilder::new()
+                        .fill_color(BinaryColor::Off)
+                        .build(),
+                    target,
+                )?;
+            }
+            Direction::Idle => {}
+        }
+        let label = self.big_label();
+        #[cfg(feature = "big-font")]
+        let (font, position) = (&FONT_10X20, Point::new(9, 38));
+        // without the big font, centre the small one between the arrows
+        #[cfg(not(feature = "big-font"))]
+        let (font, position) = (&FONT_5X8, Point::new(9, 35));
+        Text::with_alignment(
+            label,
+            position,
+            MonoTextStyleBuilder::new()
+                .font(font)
+                .text_color(BinaryColor::Off)
+                .build(),
+            Alignment::Center,
+        )
+        .draw(target)?;
+        if self.show_heartbeat {
+            let color = if self.heartbeat {
+                BinaryColor::Off
+            } else {
+                BinaryColor::On
+            };
+            Pixel(Point::new(0, 0), color).draw(target)?;
+        }
+        Ok(())
+    }
+}
+
+// a compact floor + direction view for a 128x32 landing display
+pub struct LandingView<'a>(pub &'a Elevator);
+
+impl embedded_graphics::Drawable for LandingView<'_> {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        let elevator = self.0;
+        let style = PrimitiveStyleBuilder::new()
+            .fill_color(BinaryColor::On)
+            .build();
+        // 128x32
+        match elevator.shown_direction() {
+            Direction::Up(_) => {
+                Triangle::new(Point::new(96, 6), Point::new(86, 26), Point::new(106, 26))
+                    .draw_styled(&style, target)?;
+            }
+            Direction::Down(_) => {
+                Triangle::new(Point::new(96, 26), Point::new(86, 6), Point::new(106, 6))
+                    .draw_styled(&style, target)?;
+            }
+            Direction::Idle => {}
+        }
+        let label = elevator.big_label();
+        #[cfg(feature = "big-font")]
+        let (font, position) = (&FONT_10X20, Point::new(40, 23));
+        #[cfg(not(feature = "big-font"))]
+        let (font, position) = (&FONT_5X8, Point::new(40, 19));
+        Text::with_alignment(
+            label,
+            position,
+            MonoTextStyleBuilder::new()
+                .font(font)
+                .text_color(BinaryColor::On)
+                .build(),
+            Alignment::Center,
+        )
+        .draw(target)?;
+        Ok(())
+    }
+}
+
+// shared by the tests of every module that needs a car
+#[cfg(test)]
+pub(crate) mod fixture {
+    use super::*;
+    use crate::button::MockButton;
+
+    pub(crate) const FLOORS: [(i8, &str); 8] = [
+        (-2, "B2"),
+        (-1, "B1"),
+        (1, "1"),
+        (2, "2"),
+        (3, "3"),
+        (4, "4"),
+        (5, "5"),
+        (6, "6"),
+    ];
+
+    // an 8 floor car on mock buttons, starting at floor 1
+    pub(crate) fn elevator() -> (Elevator, [MockButton; 8]) {
+        let buttons: [MockButton; 8] = Default::default();
+        let elevator = Elevator::new(core::array::from_fn(|i| {
+            let button: Box<dyn LedButtonTrait> = Box::new(buttons[i].clone());
+            (FLOORS[i].0, FLOORS[i].1, button)
+        }));
+        (elevator, buttons)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixture::{elevator, FLOORS};
+    use super::*;
+    use crate::button::{LedButton, MockButton, MockPin};
+    use alloc::rc::Rc;
+
+    // advances until `done` holds, returning the ticks it took
+    fn run_until(
+        elevator: &mut Elevator,
+        limit: u32,
+        mut done: impl FnMut(&Elevator) -> bool,
+    ) -> u32 {
+        for tick in 0..limit {
+            if done(elevator) {
+                return tick;
+            }
+            elevator.advance();
+        }
+        panic!("gave up after {} ticks", limit);
+    }
+
+    // floors where the doors opened, in order, until the car has nothing left to do
+    fn served(elevator: &mut Elevator) -> Vec<i8> {
+        let opened = Rc::new(core::cell::RefCell::new(Vec::new()));
+        let log = opened.clone();
+        elevator.on_event(move |event| {
+            if let ElevatorEvent::DoorOpened(floor) = event {
+                log.borrow_mut().push(floor);
+            }
+        });
+        run_until(elevator, 10_000, |e| e.is_idle());
+        opened.take()
+    }
+
+    // every phrase handed to on_announce, as text
+    fn heard(elevator: &mut Elevator) -> Rc<core::cell::RefCell<Vec<String>>> {
+        let phrases = Rc::new(core::cell::RefCell::new(Vec::new()));
+        let log = phrases.clone();
+        elevator.on_announce(move |bytes| {
+            log.borrow_mut()
+                .push(String::from_utf8(bytes.to_vec()).unwrap());
+        });
+        phrases
+    }
+
+    // the floors named in `phrases`, in order
+    fn floors_named(phrases: &[String]) -> Vec<i8> {
+        phrases
+            .iter()
+            .filter_map(|phrase| {
+                let value = phrase.split("VAL=").nth(1)?.split(' ').next()?;
+                let number: i8 = value.parse().ok()?;
+                Some(if phrase.starts_with("chi'ka/") {
+                    -number
+                } else {
+                    number
+                })
+            })
+            .collect()
+    }
+
+    // a 128x64 frame to draw into, every pixel starting off
+    struct Screen([[bool; 128]; 64]);
+
+    impl Screen {
+        fn of(elevator: &Elevator) -> Self {
+            let mut screen = Screen([[false; 128]; 64]);
+            elevator.draw(&mut screen).unwrap();
+            screen
+        }
+
+        fn is_on(&self, x: i32, y: i32) -> bool {
+            self.0[y as usize][x as usize]
+        }
+    }
+
+    impl embedded_graphics::geometry::OriginDimensions for Screen {
+        fn size(&self) -> Size {
+            Size::new(128, 64)
+        }
+    }
+
+    impl DrawTarget for Screen {
+        type Color = BinaryColor;
+        type Error = core::convert::Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<BinaryColor>>,
+        {
+            for Pixel(point, color) in pixels {
+                if (0..128).contains(&point.x) && (0..64).contains(&point.y) {
+                    self.0[point.y as usize][point.x as usize] = color.is_on();
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn doors_open_at(floor: i8) -> impl Fn(&Elevator) -> bool {
+        move |e: &Elevator| {
+            e.current_floor() == floor && matches!(e.door_state(), DoorState::Open(_))
+        }
+    }
+
+    #[test]
+    fn pressing_a_floor_button_opens_the_doors_there() {
+        let (mut elevator, buttons) = elevator();
+        buttons[4].set_pressed(true);
+        elevator.advance();
+        buttons[4].set_pressed(false);
+        assert!(elevator.is_called(3));
+        assert!(buttons[4].led_is_on());
+        run_until(&mut elevator, 1000, doors_open_at(3));
+        run_until(&mut elevator, 1000, |e| e.is_idle());
+        assert!(!buttons[4].led_is_on());
+    }
+
+    // the test profile keeps debug assertions on, so any door movement while
+    // the car still has travel progress panics here
+    #[test]
+    fn a_round_trip_keeps_the_door_and_travel_assertions() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(6);
+        run_until(&mut elevator, 2000, doors_open_at(6));
+        elevator.call_floor(-2);
+        run_until(&mut elevator, 2000, doors_open_at(-2));
+        run_until(&mut elevator, 1000, |e| e.is_idle());
+    }
+
+    #[test]
+    fn arrival_with_leveling_opens_once_stopped() {
+        for leveling_ms in [0, 500] {
+            let (mut elevator, _buttons) = elevator();
+            elevator.set_leveling_ms(leveling_ms);
+            elevator.call_floor(2);
+            run_until(&mut elevator, 1000, |e| {
+                e.current_floor() == 2 && !e.is_moving()
+            });
+            // stopped and level, the doors wait out the delay
+            let ticks = run_until(&mut elevator, 1000, |e| e.door_state() != DoorState::Closed);
+            assert_eq!(ticks, leveling_ms / TICK_MS);
+        }
+    }
+
+    #[test]
+    fn priority_call_passes_stops_then_serves_them() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(2);
+        elevator.call_floor_priority(4, 1);
+        run_until(&mut elevator, 2000, doors_open_at(4));
+        assert!(elevator.is_called(2));
+        run_until(&mut elevator, 2000, doors_open_at(2));
+    }
+
+    #[test]
+    fn a_long_press_calls_an_uncalled_floor_and_cancels_a_called_one() {
+        // wired as in main: debounced, with long presses after 15 polls
+        let pins: [MockPin; 8] = core::array::from_fn(|_| MockPin::high());
+        let mut elevator = Elevator::new(core::array::from_fn(|i| {
+            let button =
+                LedButton::with_debounce(MockPin::high(), pins[i].clone(), 2).with_long_press(15);
+            let button: Box<dyn LedButtonTrait> = Box::new(button);
+            (FLOORS[i].0, FLOORS[i].1, button)
+        }));
+        let hold = |elevator: &mut Elevator, pin: &MockPin| {
+            pin.set(false);
+            for _ in 0..20 {
+                elevator.advance();
+            }
+            pin.set(true);
+            elevator.advance();
+        };
+        hold(&mut elevator, &pins[5]);
+        assert!(elevator.is_called(4));
+        hold(&mut elevator, &pins[5]);
+        assert!(!elevator.is_called(4));
+    }
+
+    #[test]
+    fn a_heartbeat_ends_only_a_comm_loss_recall() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.set_comm_watchdog_ms(Some(1000));
+        run_until(&mut elevator, 200, |e| {
+            e.service_state() != ServiceState::Normal
+        });
+        elevator.feed_comm();
+        assert!(elevator.service_state() == ServiceState::Normal);
+
+        elevator.recall();
+        elevator.feed_comm();
+        assert!(elevator.service_state() == (ServiceState::Recall { comm_loss: false }));
+        elevator.release_recall();
+        assert!(elevator.service_state() == ServiceState::Normal);
+    }
+
+    #[test]
+    fn eta_counts_the_door_cycle_at_each_stop_on_the_way() {
+        let opening_at = |floor: i8| {
+            move |e: &Elevator| {
+                e.current_floor() == floor && matches!(e.door_state(), DoorState::Opening(_))
+            }
+        };
+        for leveling in [0, 500] {
+            let (mut elevator, _buttons) = elevator();
+            elevator.set_leveling_ms(leveling);
+            elevator.call_floor(2);
+            elevator.call_floor(-2);
+            let eta = elevator.eta_to(-2).unwrap();
+            assert_eq!(
+                run_until(&mut elevator, 1000, opening_at(-2)) * TICK_MS,
+                eta
+            );
+        }
+        // and from partway through, with the doors open at the first stop
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(3);
+        elevator.call_floor(6);
+        run_until(&mut elevator, 1000, doors_open_at(3));
+        let eta = elevator.eta_to(6).unwrap();
+        assert_eq!(run_until(&mut elevator, 1000, opening_at(6)) * TICK_MS, eta);
+    }
+
+    #[test]
+    fn update_only_queues_what_dispatch_writes_out() {
+        let (mut elevator, buttons) = elevator();
+        let events = Rc::new(Cell::new(0));
+        let seen = events.clone();
+        elevator.on_event(move |_| seen.set(seen.get() + 1));
+        let busy = Rc::new(Cell::new(false));
+        let woke = busy.clone();
+        elevator.on_busy(move || woke.set(true));
+
+        buttons[5].set_pressed(true);
+        elevator.poll_inputs();
+        buttons[5].set_pressed(false);
+        elevator.update();
+        assert!(elevator.is_called(4) && elevator.is_moving());
+        assert!(!buttons[5].led_is_on());
+        assert_eq!((events.get(), busy.get()), (0, false));
+
+        elevator.dispatch();
+        assert!(buttons[5].led_is_on());
+        assert!(events.get() > 0 && busy.get());
+    }
+
+    #[test]
+    fn runtime_setters_refuse_unknown_floors() {
+        let (mut elevator, _buttons) = elevator();
+        assert!(!elevator.set_floor_service(7, false));
+        assert!(!elevator.goto(0));
+        assert!(elevator.set_floor_service(3, false));
+        elevator.call_floor(3);
+        assert!(!elevator.is_called(3));
+        assert!(elevator.goto(6));
+        run_until(&mut elevator, 2000, doors_open_at(6));
+    }
+
+    #[test]
+    fn a_call_at_the_current_floor_after_closing_reopens_the_doors() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(6);
+        run_until(&mut elevator, 2000, doors_open_at(6));
+        // a call below keeps a direction set once the doors have shut
+        elevator.call_floor(-2);
+        run_until(&mut elevator, 1000, |e| e.door_state() == DoorState::Closed);
+        elevator.call_floor(6);
+        elevator.advance();
+        assert_eq!(elevator.current_floor(), 6);
+        assert!(!elevator.is_moving());
+        assert!(elevator
+            .recent_events()
+            .all(|event| !matches!(event, ElevatorEvent::ShaftEnd(_))));
+        run_until(&mut elevator, 100, doors_open_at(6));
+    }
+
+    #[test]
+    fn an_unknown_floor_maps_to_no_index() {
+        let (mut elevator, _buttons) = elevator();
+        assert_eq!(elevator.try_floor_to_index(-2), Some(0));
+        assert_eq!(elevator.try_floor_to_index(6), Some(7));
+        assert_eq!(elevator.try_floor_to_index(0), None);
+        assert_eq!(elevator.try_floor_to_index(7), None);
+        assert!(!elevator.call_floor(7));
+        assert!(!elevator.inject_press(-3));
+        assert!(!elevator.is_called(7));
+    }
+
+    #[test]
+    fn a_paused_trip_holds_its_progress_then_finishes() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(3);
+        run_until(
+            &mut elevator,
+            100,
+            |e| matches!(e.direction(), Direction::Up(Some(progress)) if progress > 0),
+        );
+        elevator.pause_travel();
+        let held = (elevator.current_floor(), elevator.direction());
+        for _ in 0..50 {
+            elevator.advance();
+            assert_eq!((elevator.current_floor(), elevator.direction()), held);
+        }
+        elevator.resume_travel();
+        run_until(&mut elevator, 1000, doors_open_at(3));
+    }
+
+    #[test]
+    fn reopening_while_closing_resumes_from_the_same_gap() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(2);
+        run_until(&mut elevator, 1000, |e| {
+            e.door_state() == DoorState::Closing(40)
+        });
+        assert!(elevator.set_door_open(true));
+        assert_eq!(elevator.door_state(), DoorState::Opening(60));
+        let mut last = 60;
+        run_until(&mut elevator, 100, |e| {
+            match e.door_state() {
+                DoorState::Opening(progress) => {
+                    assert!(progress >= last, "doors went back");
+                    last = progress;
                 }
+                DoorState::Open(_) => return true,
+                state => panic!("reopening passed through {:?}", state),
+            }
+            false
+        });
+        assert_eq!(elevator.current_floor(), 2);
+    }
+
+    #[test]
+    fn a_skipped_floor_is_passed_and_cannot_be_called() {
+        let (elevator, buttons) = elevator();
+        let mut elevator = elevator.skip_floor(4);
+        elevator.call_floor(4);
+        buttons[5].set_pressed(true);
+        elevator.advance();
+        buttons[5].set_pressed(false);
+        assert!(!elevator.is_called(4));
+        assert!(!buttons[5].led_is_on());
+
+        elevator.call_floor(5);
+        let (mut opened_at, mut passed_4) = (Vec::new(), false);
+        run_until(&mut elevator, 2000, |e| {
+            passed_4 |= e.current_floor() == 4;
+            if matches!(e.door_state(), DoorState::Opening(0)) {
+                opened_at.push(e.current_floor());
+            }
+            opened_at.last() == Some(&5)
+        });
+        // still travelled through, just without stopping
+        assert!(passed_4);
+        assert_eq!(opened_at, [5]);
+    }
+
+    #[test]
+    fn floor_labels_share_a_right_edge_and_fit_the_highlight() {
+        let (mut elevator, _buttons) = elevator();
+        // dark text on the lit wall; the columns right of the car marker
+        let ink = |screen: &Screen, index: i32, lit: bool| {
+            let y = 56 - index * 8;
+            (113..128)
+                .filter(|&x| (y..y + 8).any(|y| screen.is_on(x, y) == lit))
+                .collect::<Vec<_>>()
+        };
+        let screen = Screen::of(&elevator);
+        let right_edges: Vec<_> = (0..8)
+            .map(|i| ink(&screen, i, false).last().copied())
+            .collect();
+        assert!(right_edges
+            .iter()
+            .all(|&edge| edge.is_some() && edge == right_edges[0]));
+
+        // a called "B2" is drawn lit inside its box, clear of the box's left edge
+        elevator.call_floor(-2);
+        let screen = Screen::of(&elevator);
+        let lit: Vec<_> = ink(&screen, 0, true)
+            .into_iter()
+            .filter(|&x| x >= 128 - 14)
+            .collect();
+        assert!(lit.first().is_some_and(|&x| x > 128 - 14));
+        assert_eq!(lit.last().copied(), right_edges[0]);
+    }
+
+    #[test]
+    fn random_presses_never_move_the_car_with_the_doors_open() {
+        let (mut elevator, buttons) = elevator();
+        let mut rng = XorShift32::new(126);
+        let (mut moving, mut open) = (0, 0);
+        for _ in 0..20_000 {
+            let roll = rng.next_u32();
+            // now and then press a floor, open or close, for a single tick
+            let floor = (roll % 64) as usize;
+            if floor < 8 {
+                buttons[floor].set_pressed(true);
+            }
+            match roll >> 8 & 63 {
+                0 => elevator.door_command(true, false),
+                1 => elevator.door_command(false, true),
+                _ => false,
+            };
+            elevator.advance();
+            if floor < 8 {
+                buttons[floor].set_pressed(false);
+            }
+            assert!(!elevator.is_moving() || elevator.door_state() == DoorState::Closed);
+            moving += elevator.is_moving() as u32;
+            open += matches!(elevator.door_state(), DoorState::Open(_)) as u32;
+        }
+        // the run did exercise both
+        assert!(moving > 1000 && open > 1000);
+    }
+
+    #[test]
+    fn pressing_the_floor_while_its_doors_are_open_latches_nothing() {
+        let (mut elevator, buttons) = elevator();
+        elevator.call_floor(3);
+        run_until(&mut elevator, 1000, doors_open_at(3));
+        for _ in 0..10 {
+            buttons[4].set_pressed(true);
+            elevator.advance();
+            buttons[4].set_pressed(false);
+            elevator.advance();
+        }
+        // the doors close as usual and no stop survives them
+        let opened = served(&mut elevator);
+        assert!(opened.is_empty());
+        assert!(!elevator.is_called(3));
+        assert!(!buttons[4].led_is_on());
+    }
+
+    #[test]
+    fn a_car_stuck_idle_with_a_call_recovers_and_serves_it() {
+        let (mut elevator, buttons) = elevator();
+        // idle with closed doors but a call pending, as a scheduler bug could leave it
+        elevator.restore(&Snapshot::new(
+            1,
+            Direction::Idle,
+            DoorState::Closed,
+            1 << 6,
+        ));
+        assert!(elevator.is_called(5) && elevator.direction() == Direction::Idle);
+        assert_eq!(served(&mut elevator), [5]);
+        assert!(!buttons[6].led_is_on());
+    }
+
+    #[test]
+    fn goto_turns_the_car_around_and_keeps_the_other_calls() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(4);
+        elevator.call_floor(6);
+        run_until(&mut elevator, 1000, |e| e.current_floor() == 2);
+        assert!(elevator.goto(-2));
+        assert_eq!(served(&mut elevator), [-2, 4, 6]);
+    }
+
+    #[test]
+    fn manual_mode_moves_only_while_held_and_stops_at_the_next_floor() {
+        let (mut elevator, buttons) = elevator();
+        elevator.set_manual_mode(true);
+        buttons[7].set_pressed(true);
+        elevator.advance();
+        assert!(!elevator.is_called(6) && elevator.direction() == Direction::Idle);
+        buttons[7].set_pressed(false);
+        elevator.manual_up(true);
+        run_until(&mut elevator, 1000, |e| e.current_floor() == 2);
+        // let go just past floor 2, the car carries on to the next one
+        elevator.manual_up(false);
+        run_until(&mut elevator, 1000, |e| e.direction() == Direction::Idle);
+        assert_eq!(elevator.current_floor(), 3);
+        assert_eq!(elevator.door_state(), DoorState::Closed);
+        for _ in 0..100 {
+            elevator.advance();
+        }
+        assert_eq!(elevator.current_floor(), 3);
+    }
+
+    #[test]
+    fn announce_passing_names_every_floor_on_the_way() {
+        let (mut elevator, _buttons) = elevator();
+        let phrases = heard(&mut elevator);
+        elevator.set_announce_passing(true);
+        elevator.call_floor(5);
+        served(&mut elevator);
+        assert_eq!(floors_named(&phrases.borrow()), [2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn recent_events_keeps_the_last_few_oldest_first() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(3);
+        served(&mut elevator);
+        assert!(elevator.recent_events().copied().eq([
+            ElevatorEvent::DirectionChanged(Direction::Up(Some(0))),
+            ElevatorEvent::Departed(Direction::Up(Some(0))),
+            ElevatorEvent::Arrived(3),
+            ElevatorEvent::DoorOpened(3),
+            ElevatorEvent::DoorClosed(3),
+            ElevatorEvent::DirectionChanged(Direction::Idle),
+        ]));
+
+        let all = Rc::new(core::cell::RefCell::new(Vec::new()));
+        let log = all.clone();
+        elevator.on_event(move |event| log.borrow_mut().push(event));
+        for floor in [6, -2, 4, 1] {
+            elevator.call_floor(floor);
+            run_until(&mut elevator, 10_000, |e| e.is_idle());
+        }
+        let all = all.take();
+        assert!(all.len() > EVENT_LOG_SIZE);
+        assert!(elevator
+            .recent_events()
+            .eq(&all[all.len() - EVENT_LOG_SIZE..]));
+    }
+
+    #[test]
+    fn strict_door_safety_lets_a_reopen_beat_the_departure() {
+        // ticks from the doors shutting at floor 1 to the car leaving for 4
+        let departure = |strict| {
+            let (mut elevator, _buttons) = elevator();
+            elevator.set_strict_door_safety(strict);
+            elevator.call_floor(1);
+            elevator.call_floor(4);
+            run_until(&mut elevator, 1000, doors_open_at(1));
+            run_until(&mut elevator, 1000, |e| e.door_state() == DoorState::Closed);
+            run_until(&mut elevator, 10, |e| e.is_moving())
+        };
+        assert_eq!(departure(true), departure(false) + 1);
+
+        let (mut elevator, _buttons) = elevator();
+        elevator.set_strict_door_safety(true);
+        elevator.call_floor(1);
+        elevator.call_floor(4);
+        run_until(&mut elevator, 1000, doors_open_at(1));
+        run_until(&mut elevator, 1000, |e| e.door_state() == DoorState::Closed);
+        for _ in 0..departure(false) {
+            elevator.advance();
+        }
+        // the tick the car would otherwise have left on
+        assert!(!elevator.is_moving());
+        assert!(elevator.set_door_open(true));
+        elevator.advance();
+        assert!(!elevator.is_moving() && elevator.current_floor() == 1);
+        assert_eq!(served(&mut elevator), [1, 4]);
+    }
+
+    #[test]
+    fn directions_and_door_states_render_as_short_labels() {
+        for (direction, label) in [
+            (Direction::Up(Some(40)), "Up 40%"),
+            (Direction::Up(None), "Up"),
+            (Direction::Down(Some(0)), "Down 0%"),
+            (Direction::Down(None), "Down"),
+            (Direction::Idle, "Idle"),
+        ] {
+            assert_eq!(format!("{}", direction), label);
+        }
+        for (door, label) in [
+            (DoorState::Opening(40), "Opening 40%"),
+            (DoorState::Open(7), "Open"),
+            (DoorState::Closing(100), "Closing 100%"),
+            (DoorState::Closed, "Closed"),
+        ] {
+            assert_eq!(format!("{}", door), label);
+        }
+    }
+
+    #[test]
+    fn a_vented_door_holds_at_half_open_and_keeps_the_car_put() {
+        let (mut elevator, _buttons) = elevator();
+        assert!(elevator.set_door_vent(50));
+        elevator.call_floor(4);
+        for _ in 0..1000 {
+            elevator.advance();
+        }
+        assert_eq!(elevator.door_state(), DoorState::Opening(50));
+        assert!(elevator.current_floor() == 1 && !elevator.is_moving());
+        // open carries on to fully open, then the car gets on with its call
+        assert!(elevator.set_door_open(true));
+        run_until(&mut elevator, 100, |e| {
+            matches!(e.door_state(), DoorState::Open(_))
+        });
+        assert_eq!(served(&mut elevator), [4]);
+    }
+
+    #[test]
+    fn changes_between_polls_coalesce_into_one_change_set() {
+        let (mut elevator, _buttons) = elevator();
+        let last = elevator.snapshot();
+        assert!(elevator.poll_changes(&last).is_empty());
+        elevator.call_floor(3);
+        // several floors, directions and door states go by between the polls
+        run_until(&mut elevator, 1000, doors_open_at(3));
+        let changes = elevator.poll_changes(&last);
+        assert!(changes.floor && changes.direction && changes.door && changes.calls);
+        run_until(&mut elevator, 1000, |e| e.is_idle());
+        let changes = elevator.poll_changes(&last);
+        assert!(changes.floor && !changes.direction && !changes.door && !changes.calls);
+    }
+
+    #[test]
+    fn quiet_mode_says_nothing_but_still_serves_calls() {
+        let (mut elevator, buttons) = elevator();
+        let phrases = heard(&mut elevator);
+        elevator.set_quiet(true);
+        elevator.call_floor(3);
+        assert!(
+            buttons[4].led_is_on() || {
+                elevator.dispatch();
+                buttons[4].led_is_on()
             }
-        } else {
-            match self.door {
-                DoorState::Open(_) => {
-                    self.set_door(DoorState::Closing(0));
-                    true
-                }
-                _ => false,
+        );
+        assert_eq!(served(&mut elevator), [3]);
+        elevator.say(b"konnichiwa");
+        elevator.dispatch();
+        assert!(phrases.borrow().is_empty());
+
+        elevator.set_quiet(false);
+        elevator.call_floor(1);
+        served(&mut elevator);
+        assert!(!phrases.borrow().is_empty());
+    }
+
+    #[test]
+    fn announcements_are_pinned_byte_for_byte() {
+        let (mut elevator, _buttons) = elevator();
+        let phrases = heard(&mut elevator);
+        elevator.call_floor(3);
+        served(&mut elevator);
+        elevator.call_floor(-1);
+        served(&mut elevator);
+        // direction before arrival before close, on each trip
+        assert_eq!(
+            *phrases.borrow(),
+            [
+                "ueni/mairima'_su,\r",
+                "<NUMK VAL=3 COUNTER=kai>de'_su,\r",
+                "do'aga/shimarima'_su.\r",
+                "shitani/mairima'_su,\r",
+                "chi'ka/<NUMK VAL=1 COUNTER=kai>de'_su,\r",
+                "do'aga/shimarima'_su.\r",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_doors_close_only_once_every_hold_is_released() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(1);
+        run_until(&mut elevator, 100, doors_open_at(1));
+        let button = elevator.acquire_hold();
+        let obstruction = elevator.acquire_hold();
+        for _ in 0..200 {
+            elevator.advance();
+        }
+        drop(button);
+        for _ in 0..200 {
+            elevator.advance();
+        }
+        // one source let go, the other still holds
+        assert!(matches!(elevator.door_state(), DoorState::Open(_)));
+        drop(obstruction);
+        run_until(&mut elevator, 1000, |e| e.door_state() == DoorState::Closed);
+    }
+
+    #[test]
+    fn calls_just_inside_the_idle_delay_never_let_the_car_park() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.set_traffic_mode(TrafficMode::UpPeak);
+        elevator.restore(&Snapshot::new(4, Direction::Idle, DoorState::Closed, 0));
+        for _ in 0..5 {
+            run_until(&mut elevator, 1000, |e| e.is_idle());
+            for _ in 0..PARK_DELAY - 5 {
+                elevator.advance();
             }
+            assert!(elevator.current_floor() == 4 && !elevator.is_moving());
+            // a call here resets the idle time without moving the car
+            elevator.call_floor(4);
         }
+        run_until(&mut elevator, 1000, |e| e.is_idle());
+        let ticks = run_until(&mut elevator, 1000, |e| e.is_moving());
+        assert!(ticks >= PARK_DELAY);
+        assert_eq!(served(&mut elevator), []);
+        assert_eq!(elevator.current_floor(), 1);
     }
-}
 
-impl embedded_graphics::Drawable for Elevator {
-    type Color = BinaryColor;
-    type Output = ();
+    #[test]
+    fn open_wins_when_open_and_close_are_asserted_together() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(1);
+        run_until(&mut elevator, 1000, |e| {
+            matches!(e.door_state(), DoorState::Closing(_))
+        });
+        assert!(elevator.door_command(true, true));
+        assert!(matches!(elevator.door_state(), DoorState::Opening(_)));
+        run_until(&mut elevator, 100, doors_open_at(1));
+        // held open for as long as both stay asserted
+        for _ in 0..200 {
+            elevator.door_command(true, true);
+            elevator.advance();
+        }
+        assert!(matches!(elevator.door_state(), DoorState::Open(_)));
+        assert!(elevator.door_command(false, true));
+        assert!(matches!(elevator.door_state(), DoorState::Closing(_)));
+    }
 
-    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
-    where
-        D: DrawTarget<Color = Self::Color>,
-    {
-        let door_openess = match self.door {
-            DoorState::Opening(progress) => progress,
-            DoorState::Open(_) => 100,
-            DoorState::Closing(progress) => 100 - progress,
-            DoorState::Closed => 0,
+    #[test]
+    fn a_priority_call_below_turns_an_upward_car_around() {
+        for (level, order) in [(0, [6, -1]), (1, [-1, 6])] {
+            let (mut elevator, _buttons) = elevator();
+            elevator.call_floor(6);
+            run_until(&mut elevator, 1000, |e| e.current_floor() == 3);
+            assert!(matches!(elevator.direction(), Direction::Up(_)));
+            elevator.call_floor_priority(-1, level);
+            assert_eq!(served(&mut elevator), order);
+        }
+    }
+
+    #[test]
+    fn the_floor_is_named_once_the_doors_pass_the_threshold() {
+        let (mut elevator, _buttons) = elevator();
+        let phrases = heard(&mut elevator);
+        elevator.set_announce_open_at(20);
+        elevator.call_floor(3);
+        // the door state as each floor name is heard
+        let mut named_at = Vec::new();
+        run_until(&mut elevator, 1000, |e| {
+            let door = e.door_state();
+            named_at.resize(floors_named(&phrases.borrow()).len(), door);
+            e.is_idle()
+        });
+        assert_eq!(named_at.len(), 1);
+        assert!(matches!(named_at[0], DoorState::Opening(20..=29)));
+    }
+
+    #[test]
+    fn open_just_after_departure_reopens_but_not_mid_travel() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(4);
+        run_until(&mut elevator, 100, |e| e.is_moving());
+        assert!(matches!(elevator.direction(), Direction::Up(Some(p)) if p < DEPARTURE_GRACE));
+        assert!(elevator.set_door_open(true));
+        assert!(elevator.current_floor() == 1 && !elevator.is_moving());
+        run_until(&mut elevator, 100, doors_open_at(1));
+
+        run_until(
+            &mut elevator,
+            1000,
+            |e| matches!(e.direction(), Direction::Up(Some(p)) if p >= DEPARTURE_GRACE),
+        );
+        assert!(!elevator.set_door_open(true));
+        assert!(elevator.is_moving() && elevator.door_state() == DoorState::Closed);
+        assert_eq!(served(&mut elevator), [4]);
+    }
+
+    #[test]
+    fn announce_every_names_only_every_nth_floor_passed() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.restore(&Snapshot::new(-2, Direction::Idle, DoorState::Closed, 0));
+        let phrases = heard(&mut elevator);
+        elevator.set_announce_every(2);
+        elevator.call_floor(6);
+        served(&mut elevator);
+        // -1, 1, 2, 3, 4 and 5 are passed; the stop at 6 is always named
+        assert_eq!(floors_named(&phrases.borrow()), [1, 3, 5, 6]);
+    }
+
+    #[test]
+    fn garbage_snapshots_are_clamped_and_the_car_keeps_going() {
+        let garbage = [
+            Snapshot::new(3, Direction::Idle, DoorState::Open(200), 0),
+            Snapshot::new(2, Direction::Up(Some(137)), DoorState::Closed, 1 << 5),
+            Snapshot::new(
+                42,
+                Direction::Down(Some(255)),
+                DoorState::Closing(101),
+                0xff,
+            ),
+            Snapshot::new(6, Direction::Up(Some(50)), DoorState::Closed, 0),
+            Snapshot::new(-2, Direction::Down(Some(0)), DoorState::Opening(150), 1),
+        ];
+        for snapshot in garbage {
+            let (mut elevator, _buttons) = elevator();
+            elevator.restore(&snapshot);
+            assert!(elevator
+                .recent_events()
+                .any(|e| *e == ElevatorEvent::StateClamped));
+            run_until(&mut elevator, 10_000, |e| e.is_idle());
+            // and still answers a call afterwards
+            let floor = if elevator.current_floor() == 6 { -2 } else { 6 };
+            elevator.call_floor(floor);
+            run_until(&mut elevator, 10_000, doors_open_at(floor));
+        }
+    }
+
+    #[test]
+    fn the_car_runs_through_an_express_zone_without_stopping() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(4);
+        elevator.set_express_zone(1, 5);
+        // the pending call inside the zone is dropped, new ones are refused
+        assert!(!elevator.is_called(4));
+        elevator.call_floor(3);
+        assert!(!elevator.is_called(3));
+        elevator.call_floor(6);
+        assert_eq!(served(&mut elevator), [6]);
+
+        elevator.clear_express_zone();
+        elevator.call_floor(3);
+        assert_eq!(served(&mut elevator), [3]);
+    }
+
+    #[test]
+    fn an_idle_car_parks_where_the_current_window_says() {
+        // the lobby in the morning, floor 4 overnight, nowhere in particular otherwise
+        let windows = [(7 * 60, 10 * 60, 1), (22 * 60, 6 * 60, 4)];
+        for (hour, park) in [(8, 1), (23, 4), (3, 4), (12, 5)] {
+            let (mut elevator, _buttons) = elevator();
+            elevator.set_parking_schedule(&windows);
+            elevator.set_time(hour, 0);
+            elevator.restore(&Snapshot::new(5, Direction::Idle, DoorState::Closed, 0));
+            for _ in 0..PARK_DELAY + 1000 {
+                elevator.advance();
+            }
+            assert!(elevator.is_idle());
+            assert_eq!(elevator.current_floor(), park, "at {}:00", hour);
+        }
+    }
+
+    #[test]
+    fn will_stop_here_tells_this_pass_from_the_return_pass() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.restore(&Snapshot::new(2, Direction::Idle, DoorState::Closed, 0));
+        elevator.call_floor(5);
+        elevator.call_floor(3);
+        run_until(&mut elevator, 100, |e| e.is_moving());
+        assert!(matches!(elevator.direction(), Direction::Up(_)));
+        elevator.call_floor(1);
+        // all three are called, but 1 waits for the way back down
+        assert!(elevator.will_stop_here(3) && elevator.will_stop_here(5));
+        assert!(elevator.is_called(1) && !elevator.will_stop_here(1));
+        assert!(!elevator.will_stop_here(4));
+        run_until(&mut elevator, 1000, doors_open_at(5));
+        run_until(&mut elevator, 1000, |e| e.is_moving());
+        assert!(elevator.will_stop_here(1));
+    }
+
+    #[test]
+    fn a_floor_taken_out_of_service_mid_queue_is_skipped() {
+        let (mut elevator, buttons) = elevator();
+        for floor in [3, 4, 6] {
+            elevator.call_floor(floor);
+        }
+        run_until(&mut elevator, 1000, |e| e.current_floor() == 2);
+        assert!(elevator.set_floor_service(4, false));
+        assert!(!elevator.is_called(4));
+        // a fresh press there is refused too
+        elevator.inject_press(4);
+        elevator.advance();
+        assert!(!elevator.is_called(4));
+        assert_eq!(served(&mut elevator), [3, 6]);
+        assert!(!buttons[5].led_is_on());
+
+        assert!(elevator.set_floor_service(4, true));
+        elevator.call_floor(4);
+        assert_eq!(served(&mut elevator), [4]);
+    }
+
+    #[test]
+    fn on_idle_fires_once_per_trip_and_on_busy_once_per_call() {
+        let (mut elevator, _buttons) = elevator();
+        let idle = Rc::new(Cell::new(0));
+        let busy = Rc::new(Cell::new(0));
+        let count = idle.clone();
+        elevator.on_idle(move || count.set(count.get() + 1));
+        let count = busy.clone();
+        elevator.on_busy(move || count.set(count.get() + 1));
+        elevator.call_floor(4);
+        elevator.call_floor(6);
+        run_until(&mut elevator, 10_000, |e| e.is_idle());
+        // staying idle doesn't fire it again
+        for _ in 0..500 {
+            elevator.advance();
+        }
+        assert_eq!((busy.get(), idle.get()), (1, 1));
+    }
+
+    #[test]
+    fn an_adjacent_stop_during_a_peak_gets_a_shorter_dwell() {
+        // ticks the doors stay open at each of two stops
+        let dwells = |mode, second| {
+            let (mut elevator, _buttons) = elevator();
+            elevator.set_traffic_mode(mode);
+            elevator.set_adjacent_dwell_percent(50);
+            elevator.call_floor(3);
+            elevator.call_floor(second);
+            [3, second].map(|floor| {
+                run_until(&mut elevator, 1000, doors_open_at(floor));
+                run_until(&mut elevator, 1000, |e| {
+                    matches!(e.door_state(), DoorState::Closing(_))
+                })
+            })
         };
-        let margin = 20u32;
-        let door_width = (100 - door_openess) as u32 * (128 - margin * 2) / 100;
-        let door_style = PrimitiveStyleBuilder::new()
-            .fill_color(BinaryColor::On)
-            .build();
-        Rectangle::new(
-            Point::new(0, 0),
-            embedded_graphics::geometry::Size::new(door_width + margin, 64),
-        )
-        .draw_styled(&door_style, target)?;
-        Rectangle::new(
-            Point::new(128 - margin as i32 - door_width as i32, 0),
-            embedded_graphics::geometry::Size::new(door_width + margin, 64),
-        )
-        .draw_styled(&door_style, target)?;
+        let [first, second] = dwells(TrafficMode::UpPeak, 4);
+        // half the dwell, give or take the tick the doors finish opening on
+        assert!((first / 2..=first / 2 + 1).contains(&second));
+        let [first, second] = dwells(TrafficMode::UpPeak, 5);
+        assert_eq!(second, first);
+        let [first, second] = dwells(TrafficMode::Normal, 4);
+        assert_eq!(second, first);
+    }
 
-        let background_style_highlighted = PrimitiveStyleBuilder::new()
-            .fill_color(BinaryColor::Off)
-            .build();
+    #[test]
+    fn shutdown_mid_trip_parks_at_the_lobby_and_switches_off() {
+        let (mut elevator, buttons) = elevator();
+        elevator.call_floor(6);
+        elevator.call_floor(5);
+        run_until(&mut elevator, 1000, |e| e.current_floor() == 4);
+        elevator.shutdown();
+        assert!(elevator.service_state() == ServiceState::ShuttingDown);
+        run_until(&mut elevator, 10_000, |e| {
+            e.service_state() == ServiceState::Off
+        });
+        assert_eq!(elevator.current_floor(), 1);
+        assert!(elevator.is_idle());
+        assert!(buttons.iter().all(|button| !button.led_is_on()));
 
-        let text_style_highlighted = MonoTextStyleBuilder::new()
-            .font(&FONT_5X8)
-            .text_color(BinaryColor::On)
-            .build();
-        let text_style = MonoTextStyleBuilder::new()
-            .font(&FONT_5X8)
-            .text_color(BinaryColor::Off)
-            .build();
+        // nothing moves it any more
+        buttons[7].set_pressed(true);
+        elevator.advance();
+        buttons[7].set_pressed(false);
+        elevator.call_floor(6);
+        assert!(!elevator.is_called(6));
+        assert!(!elevator.set_door_open(true));
+        for _ in 0..500 {
+            elevator.advance();
+        }
+        assert!(elevator.is_idle() && elevator.current_floor() == 1);
+    }
 
-        // 128x64
-        for i in 0..self.floors.len() {
-            let floor = &self.floors[i];
-            let y = 56 - i as i32 * 8;
-            let width = 5 * floor.label.len() as i32;
-            if floor.stop {
-                Rectangle::new(Point::new(128 - 14, y), Size::new(14, 8))
-                    .draw_styled(&background_style_highlighted, target)?;
-                Text::new(
-                    floor.label,
-                    Point::new(128 - width - 2, y + 6),
-                    text_style_highlighted,
-                )
-                .draw(target)?;
-            } else {
-                Text::new(floor.label, Point::new(128 - width - 2, y + 6), text_style)
-                    .draw(target)?;
+    #[test]
+    fn the_direction_is_repeated_while_the_doors_stay_open() {
+        for quiet in [false, true] {
+            let (mut elevator, _buttons) = elevator();
+            let phrases = heard(&mut elevator);
+            elevator.set_reannounce_interval_ms(3000);
+            elevator.set_quiet(quiet);
+            elevator.call_floor(4);
+            elevator.call_floor(6);
+            run_until(&mut elevator, 1000, doors_open_at(4));
+            let heard_before = phrases.borrow().len();
+            // held for 4 s on top of the usual 5 s dwell
+            let hold = elevator.acquire_hold();
+            for _ in 0..4000 / TICK_MS {
+                elevator.advance();
             }
-            if i == self.current_floor_index {
-                let y = match self.direction {
-                    Direction::Up(value) => {
-                        if let Some(progress) = value {
-                            y - progress as i32 * 8 / 100
-                        } else {
-                            y
-                        }
-                    }
-                    Direction::Down(value) => {
-                        if let Some(progress) = value {
-                            y + progress as i32 * 8 / 100
-                        } else {
-                            y
-                        }
-                    }
-                    Direction::Idle => y,
-                };
-                Triangle::new(
-                    Point::new(128 - margin as i32 + 1, y + 2),
-                    Point::new(128 - margin as i32 + 1, y + 6),
-                    Point::new(128 - margin as i32 + 4, y + 4),
-                )
-                .draw_styled(&background_style_highlighted, target)?;
+            drop(hold);
+            run_until(&mut elevator, 1000, |e| {
+                matches!(e.door_state(), DoorState::Closing(_))
+            });
+            let repeats = phrases.borrow()[heard_before..]
+                .iter()
+                .filter(|phrase| phrase.starts_with("ueni/"))
+                .count();
+            assert_eq!(repeats, if quiet { 0 } else { 3 });
+        }
+    }
+
+    #[test]
+    fn a_call_mask_round_trips_and_gets_served() {
+        let (mut elevator, buttons) = elevator();
+        // floors 3, 4 and 6, plus bits past the top floor
+        let mask = 1 << 4 | 1 << 5 | 1 << 7;
+        elevator.set_call_mask(mask | 0xff00);
+        assert_eq!(elevator.call_mask(), mask);
+        elevator.dispatch();
+        assert!((0..8).all(|i| buttons[i].led_is_on() == (mask & 1 << i != 0)));
+        assert_eq!(served(&mut elevator), [3, 4, 6]);
+        assert_eq!(elevator.call_mask(), 0);
+        elevator.set_call_mask(mask);
+        elevator.set_call_mask(1 << 4);
+        assert_eq!(elevator.call_mask(), 1 << 4);
+    }
+
+    #[test]
+    fn closing_can_be_silenced_alone() {
+        let (mut elevator, _buttons) = elevator();
+        let phrases = heard(&mut elevator);
+        elevator.set_announce_closing(false);
+        elevator.call_floor(3);
+        served(&mut elevator);
+        assert_eq!(
+            *phrases.borrow(),
+            ["ueni/mairima'_su,\r", "<NUMK VAL=3 COUNTER=kai>de'_su,\r"]
+        );
+    }
+
+    #[test]
+    fn a_landing_summon_is_served_like_a_cabin_call() {
+        let (elevator, _buttons) = elevator();
+        let landing = MockButton::default();
+        let mut elevator = elevator.with_summon_button(5, landing.clone());
+        landing.set_pressed(true);
+        elevator.advance();
+        landing.set_pressed(false);
+        assert!(elevator.is_called(5) && elevator.is_summoned(5));
+        assert_eq!(served(&mut elevator), [5]);
+        assert!(!elevator.is_summoned(5));
+
+        // a cabin call is the same stop, just not a summon
+        elevator.call_floor(2);
+        assert!(elevator.is_called(2) && !elevator.is_summoned(2));
+        assert!(elevator.summon(-1));
+        assert_eq!(served(&mut elevator), [2, -1]);
+    }
+
+    #[test]
+    fn closed_doors_cover_the_middle_and_open_ones_retract_to_the_margins() {
+        let (mut elevator, _buttons) = elevator();
+        let screen = Screen::of(&elevator);
+        assert!((0..64).all(|y| (20..108).all(|x| screen.is_on(x, y))));
+
+        elevator.call_floor(1);
+        run_until(&mut elevator, 100, |e| {
+            matches!(e.door_state(), DoorState::Open(_))
+        });
+        let screen = Screen::of(&elevator);
+        assert!((0..64).all(|y| (20..108).all(|x| !screen.is_on(x, y))));
+        // the wall either side stays
+        assert!((0..20).chain(108..128).all(|x| screen.is_on(x, 0)));
+    }
+
+    #[test]
+    fn each_door_style_draws_its_leaves_at_0_50_and_100_percent() {
+        // which half of the doorway, left then right, is covered by a leaf
+        let covered = |style, openness| {
+            let (mut elevator, _buttons) = elevator();
+            elevator.set_door_style(style);
+            elevator.restore(&Snapshot::new(
+                1,
+                Direction::Idle,
+                DoorState::Opening(openness),
+                0,
+            ));
+            let screen = Screen::of(&elevator);
+            [20..64, 64..108].map(|half| {
+                let lit = half.filter(|&x| screen.is_on(x, 0)).count();
+                assert!(lit == 0 || lit == 44, "a leaf edge inside a half");
+                lit == 44
+            })
+        };
+        for style in [
+            DoorStyle::CenterOpening,
+            DoorStyle::SideOpeningLeft,
+            DoorStyle::SideOpeningRight,
+        ] {
+            assert_eq!(covered(style, 0), [true, true]);
+            assert_eq!(covered(style, 100), [false, false]);
+        }
+        // both centre leaves reach the middle until past half open
+        assert_eq!(covered(DoorStyle::CenterOpening, 50), [true, true]);
+        assert_eq!(covered(DoorStyle::SideOpeningLeft, 50), [true, false]);
+        assert_eq!(covered(DoorStyle::SideOpeningRight, 50), [false, true]);
+    }
+
+    #[test]
+    fn the_idle_arrow_shows_until_a_real_direction_replaces_it() {
+        // pixels inside the up and down arrows, whatever their animation step
+        let arrows = |elevator: &Elevator| {
+            let screen = Screen::of(elevator);
+            (!screen.is_on(9, 12), !screen.is_on(9, 52))
+        };
+        let (mut elevator, _buttons) = elevator();
+        assert_eq!(arrows(&elevator), (false, false));
+        elevator.set_idle_arrow(Some(Direction::Up(None)));
+        assert_eq!(arrows(&elevator), (true, false));
+        // the car itself is unaffected
+        for _ in 0..100 {
+            elevator.advance();
+        }
+        assert!(elevator.is_idle() && arrows(&elevator) == (true, false));
+
+        elevator.call_floor(-2);
+        for _ in 0..20 {
+            elevator.advance();
+            if elevator.is_moving() {
+                assert_eq!(arrows(&elevator), (false, true));
             }
         }
+        assert!(elevator.is_moving());
+    }
 
-        match self.direction {
-            Direction::Up(value) => {
-                let dy = if let Some(progress) = value {
-                    progress as i32 / 5 % 10
-                } else {
-                    0
-                };
-                let y = 10 - dy;
-                let height = 13;
-                let width = 7;
-                Triangle::new(
-                    Point::new(margin as i32 / 2 - 1, y),
-                    Point::new(margin as i32 / 2 - 1 - width, y + height),
-                    Point::new(margin as i32 / 2 - 1 + width, y + height),
-                )
-                .draw_styled(
-                    &PrimitiveStyleBuilder::new()
-                        .fill_color(BinaryColor::Off)
-                        .build(),
-                    target,
-                )?;
+    #[test]
+    fn a_call_waiting_past_max_wait_is_taken_next() {
+        // stops made before reaching -2 while 2 and 3 keep calling each other
+        let stops_before_far_call = |max_wait_ms| {
+            let (mut elevator, _buttons) = elevator();
+            elevator.set_max_wait_ms(max_wait_ms);
+            elevator.call_floor(-2);
+            elevator.call_floor(2);
+            let mut stops = Vec::new();
+            while stops.len() < 20 {
+                run_until(&mut elevator, 1000, |e| {
+                    matches!(e.door_state(), DoorState::Opening(_))
+                });
+                let floor = elevator.current_floor();
+                if floor == -2 {
+                    return stops;
+                }
+                stops.push(floor);
+                elevator.call_floor(if floor == 2 { 3 } else { 2 });
+                run_until(&mut elevator, 1000, |e| e.door_state() == DoorState::Closed);
             }
-            Direction::Down(value) => {
-                let dy = if let Some(progress) = value {
-                    progress as i32 / 5 % 10
-                } else {
-                    0
-                };
-                let y = 41 + dy;
-                let height = 13;
-                let width = 7;
-                Triangle::new(
-                    Point::new(margin as i32 / 2 - 1, y + height),
-                    Point::new(margin as i32 / 2 - 1 - width, y),
-                    Point::new(margin as i32 / 2 - 1 + width, y),
-                )
-                .draw_styled(
-                    &PrimitiveStyleBuilder::new()
-                        .fill_color(BinaryColor::Off)
-                        .build(),
-                    target,
-                )?;
+            stops
+        };
+        // plain LOOK heads up first and only reaches -2 when it next turns down
+        assert_eq!(stops_before_far_call(0), [2, 3, 2]);
+        // by the first stop -2 has waited too long, so the car turns for it
+        assert_eq!(stops_before_far_call(1000), [2]);
+    }
+
+    #[test]
+    fn a_relabelled_floor_renders_its_new_label() {
+        let (mut elevator, _buttons) = elevator();
+        let before = Screen::of(&elevator);
+        assert!(elevator.set_label(3, "3F"));
+        assert!(!elevator.set_label(7, "7F"));
+        let after = Screen::of(&elevator);
+        // the row of floor 3 gains a character left of the old one, and nothing else changes
+        let row = 56 - 4 * 8;
+        assert!((row..row + 8).all(|y| (115..120).all(|x| before.is_on(x, y))));
+        assert!((row..row + 8).any(|y| (115..120).any(|x| !after.is_on(x, y))));
+        for y in (0..64).filter(|y| !(row..row + 8).contains(y)) {
+            assert_eq!(before.0[y as usize], after.0[y as usize]);
+        }
+
+        // the same as a car built with that label
+        let buttons: [MockButton; 8] = Default::default();
+        let built = Elevator::new(core::array::from_fn(|i| {
+            let button: Box<dyn LedButtonTrait> = Box::new(buttons[i].clone());
+            let (number, label) = FLOORS[i];
+            (number, if number == 3 { "3F" } else { label }, button)
+        }));
+        assert!(Screen::of(&built).0 == after.0);
+    }
+
+    #[test]
+    fn idle_between_two_calls_serves_the_nearer_one_first() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.restore(&Snapshot::new(3, Direction::Idle, DoorState::Closed, 0));
+        elevator.call_floor(5);
+        elevator.call_floor(2);
+        assert_eq!(served(&mut elevator), [2, 5]);
+    }
+
+    #[test]
+    fn moving_up_serves_calls_above_before_turning_for_those_below() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.restore(&Snapshot::new(2, Direction::Idle, DoorState::Closed, 0));
+        elevator.call_floor(5);
+        run_until(&mut elevator, 100, |e| e.is_moving());
+        for floor in [-1, 1, 4] {
+            elevator.call_floor(floor);
+        }
+        assert_eq!(served(&mut elevator), [4, 5, 1, -1]);
+    }
+
+    #[test]
+    fn a_call_just_behind_the_car_waits_for_the_return_pass() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(5);
+        run_until(&mut elevator, 1000, |e| e.current_floor() == 2);
+        run_until(&mut elevator, 100, |e| e.is_moving());
+        elevator.call_floor(2);
+        assert_eq!(served(&mut elevator), [5, 2]);
+    }
+
+    #[test]
+    fn calls_at_both_ends_are_served_nearer_end_first() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(6);
+        elevator.call_floor(-2);
+        assert_eq!(served(&mut elevator), [-2, 6]);
+        elevator.call_floor(-2);
+        elevator.call_floor(5);
+        // from the top, 5 is nearer and on the way down
+        assert_eq!(served(&mut elevator), [5, -2]);
+    }
+
+    #[test]
+    fn an_arrived_car_with_opening_doors_is_neither_moving_nor_idle() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.call_floor(2);
+        assert!(!elevator.is_moving() && !elevator.is_idle());
+        run_until(&mut elevator, 100, |e| e.is_moving());
+        assert!(!elevator.is_idle());
+        run_until(&mut elevator, 1000, |e| {
+            matches!(e.door_state(), DoorState::Opening(_))
+        });
+        // arrived and stopped, but the stop is still being served
+        assert_eq!(elevator.current_floor(), 2);
+        assert!(!elevator.is_moving() && !elevator.is_idle());
+        assert!(matches!(elevator.direction(), Direction::Up(None)));
+        // stopped, so an open press is honoured even though a direction is set
+        elevator.restore(&Snapshot::new(2, Direction::Up(None), DoorState::Closed, 0));
+        assert!(!elevator.is_moving() && !elevator.is_idle());
+        assert!(elevator.set_door_open(true));
+        run_until(&mut elevator, 1000, |e| e.is_idle());
+        assert!(!elevator.is_moving());
+    }
+
+    #[test]
+    fn a_jammed_open_button_is_overridden_and_the_car_leaves() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.set_max_door_open_ms(3000);
+        let said = heard(&mut elevator);
+        let mut hold = None;
+        let mut departed = false;
+        for _ in 0..400 {
+            // what the main loop does with the open button held down throughout
+            elevator.door_command(true, false);
+            hold.get_or_insert_with(|| elevator.acquire_hold());
+            if elevator.door_state() == DoorState::Open(0) && !elevator.is_called(3) {
+                elevator.call_floor(3);
+            }
+            elevator.advance();
+            if elevator.is_moving() {
+                departed = true;
+                break;
             }
-            Direction::Idle => {}
         }
-        let label = self.floors[self.current_floor_index].label;
-        Text::new(
-            label,
-            Point::new(10 - label.len() as i32 * 10 / 2, 38),
-            MonoTextStyleBuilder::new()
-                .font(&FONT_10X20)
-                .text_color(BinaryColor::Off)
-                .build(),
-        )
-        .draw(target)?;
-        Ok(())
+        assert!(departed, "the doors kept reopening at floor 1");
+        assert!(said.borrow().iter().any(|p| p.starts_with("gochu'ui")));
+        assert_eq!(elevator.door_state(), DoorState::Closed);
+    }
+
+    #[test]
+    fn a_call_passed_for_a_goto_flashes_and_the_car_eases_off() {
+        // ticks to reach floor 5 past a call at 3, and whether 3's LED went dark meanwhile
+        let trip = |acknowledge: bool| {
+            let (mut elevator, buttons) = elevator();
+            elevator.set_pass_acknowledge(acknowledge);
+            elevator.call_floor(3);
+            elevator.goto(5);
+            let mut flashed = false;
+            let ticks = run_until(&mut elevator, 1000, |e| {
+                if e.current_floor() == 5 {
+                    return true;
+                }
+                assert_eq!(e.door_state(), DoorState::Closed, "stopped on the way");
+                flashed |= e.current_floor() > 1 && e.is_called(3) && !buttons[4].led_is_on();
+                false
+            });
+            elevator.advance();
+            assert!(elevator.is_called(3), "the passed call is kept");
+            (ticks, flashed)
+        };
+        let (plain, plain_flashed) = trip(false);
+        let (acknowledged, flashed) = trip(true);
+        assert!(!plain_flashed);
+        assert!(flashed);
+        assert!(acknowledged > plain, "{} vs {} ticks", acknowledged, plain);
+    }
+
+    #[test]
+    fn an_open_queued_during_a_forced_close_is_dropped() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.set_max_door_open_ms(1000);
+        elevator.set_door_open(true);
+        run_until(&mut elevator, 100, |e| {
+            matches!(e.door_state(), DoorState::Closing(_))
+        });
+        elevator.request_open_at_stop();
+        run_until(&mut elevator, 100, |e| e.door_state() == DoorState::Closed);
+        for _ in 0..20 {
+            elevator.advance();
+            assert_eq!(elevator.door_state(), DoorState::Closed);
+        }
+    }
+
+    #[test]
+    fn a_recall_while_closing_at_the_lobby_reopens_the_doors() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.set_door_open(true);
+        run_until(
+            &mut elevator,
+            200,
+            |e| matches!(e.door_state(), DoorState::Closing(p) if p >= 50),
+        );
+        elevator.recall();
+        run_until(&mut elevator, 100, |e| e.is_recalled());
+        assert!(matches!(elevator.door_state(), DoorState::Open(_)));
+    }
+
+    #[test]
+    fn an_overload_holds_the_doors_and_a_mid_trip_pause_reports_an_emergency() {
+        let (mut elevator, _buttons) = elevator();
+        let events = Rc::new(core::cell::RefCell::new(Vec::new()));
+        let log = events.clone();
+        elevator.on_event(move |event| log.borrow_mut().push(event));
+
+        elevator.call_floor(3);
+        elevator.set_door_open(true);
+        run_until(&mut elevator, 100, |e| {
+            matches!(e.door_state(), DoorState::Open(_))
+        });
+        elevator.set_overload(true);
+        elevator.set_overload(true);
+        assert!(!elevator.set_door_open(false));
+        for _ in 0..200 {
+            elevator.advance();
+            assert!(matches!(elevator.door_state(), DoorState::Open(_)));
+        }
+        elevator.set_overload(false);
+        run_until(&mut elevator, 500, |e| e.is_moving());
+        elevator.pause_travel();
+        elevator.pause_travel();
+        elevator.advance();
+        let reported: Vec<_> = events
+            .borrow()
+            .iter()
+            .filter(|e| matches!(e, ElevatorEvent::Overload | ElevatorEvent::Emergency))
+            .copied()
+            .collect();
+        assert_eq!(
+            reported,
+            [ElevatorEvent::Overload, ElevatorEvent::Emergency]
+        );
+    }
+
+    #[test]
+    fn a_floor_pinned_to_english_is_named_in_english() {
+        let (elevator, _buttons) = elevator();
+        let mut elevator = elevator.with_floor_language(3, Language::English);
+        let said = heard(&mut elevator);
+        elevator.call_floor(3);
+        run_until(&mut elevator, 1000, doors_open_at(3));
+        elevator.call_floor(2);
+        run_until(&mut elevator, 1000, doors_open_at(2));
+        assert!(said.borrow().contains(&"fu'roa/su'rii,\r".into()));
+        assert_eq!(floors_named(&said.borrow()), [2]);
+        // the other phrases follow the car's language
+        assert!(said.borrow().contains(&"ueni/mairima'_su,\r".into()));
+
+        // and the whole car can switch over
+        elevator.set_language(Language::English);
+        said.borrow_mut().clear();
+        elevator.call_floor(-1);
+        run_until(&mut elevator, 1000, doors_open_at(-1));
+        assert!(said.borrow().contains(&"go'uingu/da'un,\r".into()));
+        assert!(said.borrow().contains(&"be'esumento/wa'n,\r".into()));
+    }
+
+    #[test]
+    fn a_vent_stops_on_its_mark_and_still_counts_as_open_time() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.set_max_door_open_ms(3000);
+        assert!(elevator.set_door_vent(52));
+        let mut widest = 0;
+        run_until(&mut elevator, 100, |e| {
+            if let DoorState::Opening(progress) = e.door_state() {
+                assert!(progress >= widest && progress <= 52, "at {}", progress);
+                widest = progress;
+            }
+            widest == 52
+        });
+        assert_eq!(elevator.phase_remaining_ms(), None);
+        // forced shut once held past the limit, and not reopened by the open button
+        run_until(&mut elevator, 100, |e| {
+            matches!(e.door_state(), DoorState::Closing(_))
+        });
+        assert!(!elevator.set_door_open(true));
+        run_until(&mut elevator, 100, |e| e.door_state() == DoorState::Closed);
+    }
+
+    #[test]
+    fn opening_during_leveling_opens_only_once() {
+        let (mut elevator, _buttons) = elevator();
+        elevator.set_leveling_ms(1000);
+        elevator.call_floor(3);
+        run_until(&mut elevator, 1000, |e| e.current_floor() == 3);
+        assert_eq!(elevator.door_state(), DoorState::Closed);
+        assert!(elevator.set_door_open(true));
+        assert_eq!(served(&mut elevator), [3]);
+    }
+
+    #[test]
+    fn the_travel_tone_rises_and_falls_with_the_ramped_speed() {
+        struct Recorder(Rc<core::cell::RefCell<Vec<SoundEffect>>>);
+        impl Sound for Recorder {
+            fn play(&mut self, effect: SoundEffect) {
+                self.0.borrow_mut().push(effect);
+            }
+        }
+        let trip = |ramp: bool| {
+            let (mut elevator, _buttons) = elevator();
+            let played = Rc::new(core::cell::RefCell::new(Vec::new()));
+            elevator.set_sound(Recorder(played.clone()));
+            elevator.set_travel_tone(true);
+            elevator.set_acceleration_ramp(ramp);
+            elevator.call_floor(3);
+            let ticks = run_until(&mut elevator, 1000, doors_open_at(3));
+            (ticks, played.take())
+        };
+        let (steady_ticks, steady) = trip(false);
+        assert_eq!(
+            steady,
+            [SoundEffect::Travel(TONE_MAX_HZ), SoundEffect::Silence]
+        );
+
+        let (ticks, played) = trip(true);
+        assert!(ticks > steady_ticks);
+        assert_eq!(played.last(), Some(&SoundEffect::Silence));
+        let tones: Vec<u32> = played
+            .iter()
+            .filter_map(|effect| match effect {
+                SoundEffect::Travel(hz) => Some(*hz),
+                SoundEffect::Silence => None,
+            })
+            .collect();
+        // up to full pitch and back down, never refreshed more than every few ticks
+        let top = tones.iter().position(|&hz| hz == TONE_MAX_HZ).unwrap();
+        assert!(tones[..=top].windows(2).all(|w| w[0] < w[1]));
+        assert!(tones[top..].windows(2).all(|w| w[0] >= w[1]));
+        assert!(tones[0] < TONE_MAX_HZ && *tones.last().unwrap() < TONE_MAX_HZ);
+        assert!(tones.len() as u32 <= ticks / TONE_UPDATE_TICKS + 1);
+    }
+
+    #[test]
+    fn dimming_and_led_animations_advance_in_update_and_show_in_dispatch() {
+        let (mut elevator, buttons) = elevator();
+        elevator.set_attract_mode(true);
+        let contrast = Rc::new(Cell::new(None));
+        let sink = contrast.clone();
+        elevator.on_brightness(move |value| sink.set(Some(value)));
+        for _ in 0..DIM_DELAY + 1 {
+            elevator.update();
+        }
+        // the chase has started and the display dimmed, but nothing is written yet
+        assert_eq!(contrast.get(), None);
+        assert!(buttons.iter().all(|b| !b.led_is_on()));
+        elevator.dispatch();
+        assert_eq!(contrast.get(), Some(DIM_CONTRAST));
+        assert_eq!(buttons.iter().filter(|b| b.led_is_on()).count(), 1);
+    }
+
+    #[test]
+    fn a_level_sensor_corrects_the_modelled_floor() {
+        use crate::button::InputOnly;
+        let sensor = MockPin::high();
+        let (elevator, _buttons) = elevator();
+        let mut elevator = elevator.with_level_sensor(2, InputOnly::new(sensor.clone()));
+        elevator.advance();
+        assert_eq!(elevator.current_floor(), 1);
+        sensor.set(false);
+        elevator.advance();
+        assert_eq!(
+            (elevator.current_floor(), elevator.level_mismatches()),
+            (2, 1)
+        );
+    }
+
+    #[test]
+    fn a_served_floor_with_a_pwm_led_fades_out() {
+        use crate::button::{MockPwm, PwmLed, WithLed};
+        let pwm = MockPwm::default();
+        let buttons: [MockButton; 8] = Default::default();
+        let mut elevator = Elevator::new(core::array::from_fn(|i| {
+            let button: Box<dyn LedButtonTrait> = if FLOORS[i].0 == 3 {
+                Box::new(WithLed::new(
+                    buttons[i].clone(),
+                    PwmLed::new(pwm.clone()).with_fade(5),
+                ))
+            } else {
+                Box::new(buttons[i].clone())
+            };
+            (FLOORS[i].0, FLOORS[i].1, button)
+        }));
+        elevator.call_floor(3);
+        elevator.advance();
+        assert_eq!(pwm.duty(), MockPwm::MAX_DUTY);
+        run_until(&mut elevator, 1000, |e| {
+            e.current_floor() == 3 && e.door_state() == DoorState::Closed && !e.is_called(3)
+        });
+        let mut duties = vec![pwm.duty()];
+        for _ in 0..6 {
+            elevator.advance();
+            duties.push(pwm.duty());
+        }
+        assert!(duties[0] > 0, "snapped off");
+        assert!(duties.windows(2).all(|w| w[1] < w[0] || w[1] == 0));
+        assert_eq!(duties.last(), Some(&0));
     }
 }
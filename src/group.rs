@@ -0,0 +1,124 @@
+use crate::elevator::Elevator;
+use alloc::vec::Vec;
+
+// cost of each stop a car already has planned, in floors of travel
+const STOP_COST: usize = 2;
+
+pub struct ElevatorGroup {
+    cars: Vec<Elevator>,
+    // landing calls and the car each one was assigned to
+    hall_calls: Vec<(i8, usize)>,
+}
+
+impl ElevatorGroup {
+    pub fn new(cars: Vec<Elevator>) -> Self {
+        Self {
+            cars,
+            hall_calls: Vec::new(),
+        }
+    }
+
+    pub fn cars(&self) -> &[Elevator] {
+        &self.cars
+    }
+
+    pub fn car_mut(&mut self, index: usize) -> &mut Elevator {
+        &mut self.cars[index]
+    }
+
+    // assigns a landing call to the car that can serve it soonest
+    pub fn hall_call(&mut self, floor: i8) -> bool {
+        if self.hall_calls.iter().any(|(f, _)| *f == floor) {
+            return true;
+        }
+        let best = self
+            .cars
+            .iter()
+            .enumerate()
+            .filter_map(|(i, car)| Some((i, Self::cost(car, floor)?)))
+            .min_by_key(|(_, cost)| *cost);
+        match best {
+            Some((car, _)) => {
+                self.cars[car].call_floor(floor);
+                self.hall_calls.push((floor, car));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn hall_call_car(&self, floor: i8) -> Option<usize> {
+        self.hall_calls
+            .iter()
+            .find(|(f, _)| *f == floor)
+            .map(|(_, car)| *car)
+    }
+
+    fn cost(car: &Elevator, floor: i8) -> Option<usize> {
+        let target = car.try_floor_to_index(floor)?;
+        let order = car.service_order();
+        if order.contains(&floor) {
+            return Some(0);
+        }
+        let current = car.floor_to_index(car.current_floor());
+        Some(current.abs_diff(target) + order.len() * STOP_COST)
+    }
+
+    pub fn recall_all(&mut self) {
+        self.hall_calls.clear();
+        for car in self.cars.iter_mut() {
+            car.recall();
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        for car in self.cars.iter_mut() {
+            car.release_recall();
+        }
+    }
+
+    // every car has reached the lobby after recall_all
+    pub fn all_recalled(&self) -> bool {
+        self.cars.iter().all(|car| car.is_recalled())
+    }
+
+    pub fn advance(&mut self) {
+        for car in self.cars.iter_mut() {
+            car.advance();
+        }
+        let cars = &self.cars;
+        self.hall_calls
+            .retain(|(floor, car)| cars[*car].is_called(*floor));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elevator::fixture::elevator;
+    use crate::elevator::DoorState;
+    use alloc::vec;
+
+    #[test]
+    fn recall_all_brings_in_a_car_closing_at_the_lobby() {
+        let (mut closing, _closing_buttons) = elevator();
+        closing.set_door_open(true);
+        while !matches!(closing.door_state(), DoorState::Closing(p) if p >= 50) {
+            closing.advance();
+        }
+        let (mut away, _away_buttons) = elevator();
+        away.goto(4);
+        let mut group = ElevatorGroup::new(vec![closing, away]);
+        for _ in 0..100 {
+            group.advance();
+        }
+        group.recall_all();
+        for _ in 0..1000 {
+            if group.all_recalled() {
+                return;
+            }
+            group.advance();
+        }
+        panic!("not every car reached the lobby");
+    }
+}
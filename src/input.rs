@@ -0,0 +1,136 @@
+use crate::button::ButtonError;
+use crate::elevator::Elevator;
+use embedded_hal::digital::v2::InputPin;
+
+// steps indexed by (previous state << 2) | current state, where a state is (a << 1) | b
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+const STEPS_PER_DETENT: i8 = 4;
+
+pub struct RotaryEncoder<A, B> {
+    a: A,
+    b: B,
+    state: u8,
+    steps: i8,
+}
+
+impl<A, B> RotaryEncoder<A, B>
+where
+    A: InputPin,
+    B: InputPin,
+{
+    pub fn new(a: A, b: B) -> Self {
+        let mut encoder = Self {
+            a,
+            b,
+            state: 0,
+            steps: 0,
+        };
+        encoder.state = encoder.read().unwrap_or(0);
+        encoder
+    }
+
+    fn read(&self) -> Result<u8, ButtonError> {
+        let a = self.a.is_high().map_err(|_| ButtonError::Input)? as u8;
+        let b = self.b.is_high().map_err(|_| ButtonError::Input)? as u8;
+        Ok(a << 1 | b)
+    }
+
+    // returns the number of detents turned since the last poll (positive is clockwise)
+    pub fn poll(&mut self) -> Result<i8, ButtonError> {
+        let state = self.read()?;
+        self.steps += TRANSITIONS[(self.state << 2 | state) as usize];
+        self.state = state;
+        let detents = self.steps / STEPS_PER_DETENT;
+        self.steps %= STEPS_PER_DETENT;
+        Ok(detents)
+    }
+}
+
+pub struct RotaryFloorSelector<A, B, SELECT> {
+    encoder: RotaryEncoder<A, B>,
+    select: SELECT,
+    was_pressed: bool,
+}
+
+impl<A, B, SELECT> RotaryFloorSelector<A, B, SELECT>
+where
+    A: InputPin,
+    B: InputPin,
+    SELECT: InputPin,
+{
+    pub fn new(encoder: RotaryEncoder<A, B>, select: SELECT) -> Self {
+        Self {
+            encoder,
+            select,
+            was_pressed: false,
+        }
+    }
+
+    pub fn poll(&mut self, elevator: &mut Elevator) -> Result<(), ButtonError> {
+        let detents = self.encoder.poll()?;
+        if detents != 0 {
+            elevator.move_highlight(detents);
+        }
+        // the select button is active low, confirm on press
+        let pressed = self.select.is_low().map_err(|_| ButtonError::Input)?;
+        if pressed && !self.was_pressed {
+            elevator.confirm_selection();
+        }
+        self.was_pressed = pressed;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::button::MockPin;
+    use crate::elevator::fixture::elevator;
+
+    // a full quadrature cycle each way from rest, one detent
+    const CLOCKWISE: [(bool, bool); 4] =
+        [(true, false), (true, true), (false, true), (false, false)];
+    const ANTICLOCKWISE: [(bool, bool); 4] =
+        [(false, true), (true, true), (true, false), (false, false)];
+
+    fn turn(
+        encoder: &mut RotaryEncoder<MockPin, MockPin>,
+        (a, b): (&MockPin, &MockPin),
+        states: impl Iterator<Item = (bool, bool)>,
+    ) -> i8 {
+        states
+            .map(|(high_a, high_b)| {
+                a.set(high_a);
+                b.set(high_b);
+                encoder.poll().unwrap()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn a_full_cycle_is_one_detent_each_way() {
+        let (a, b) = (MockPin::default(), MockPin::default());
+        let mut encoder = RotaryEncoder::new(a.clone(), b.clone());
+        assert_eq!(turn(&mut encoder, (&a, &b), CLOCKWISE.into_iter()), 1);
+        assert_eq!(turn(&mut encoder, (&a, &b), ANTICLOCKWISE.into_iter()), -1);
+        // half a detent and back is nothing
+        let wobble = [(true, false), (false, false)];
+        assert_eq!(turn(&mut encoder, (&a, &b), wobble.into_iter()), 0);
+    }
+
+    #[test]
+    fn the_selector_calls_the_highlighted_floor() {
+        let (mut elevator, _buttons) = elevator();
+        let (a, b, select) = (MockPin::default(), MockPin::default(), MockPin::high());
+        let mut selector =
+            RotaryFloorSelector::new(RotaryEncoder::new(a.clone(), b.clone()), select.clone());
+        for (high_a, high_b) in CLOCKWISE.into_iter().chain(CLOCKWISE) {
+            a.set(high_a);
+            b.set(high_b);
+            selector.poll(&mut elevator).unwrap();
+        }
+        select.set(false);
+        selector.poll(&mut elevator).unwrap();
+        assert!(elevator.is_called(3));
+    }
+}
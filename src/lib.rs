@@ -0,0 +1,19 @@
+//! The elevator logic, kept apart from the board bring-up in `main.rs`.
+//!
+//! Everything here also builds for the host so it can be unit tested:
+//! `cargo test --target x86_64-unknown-linux-gnu` (or whatever the host triple is).
+//! Not every car, button or display option is wired up on this board; the
+//! rest are here for builds that have the hardware.
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod boot;
+pub mod button;
+pub mod command;
+pub mod elevator;
+pub mod group;
+pub mod input;
+pub mod sound;
+pub mod text;
+pub mod util;
@@ -1,6 +1,9 @@
 //! Blinks the LED on a Pico board
 //!
 //! This will blink an LED attached to GP25, which is the pin the Pico uses for the on-board LED.
+//!
+//! The elevator itself lives in the library next to this file, which is where the
+//! unit tests are.
 #![no_std]
 #![no_main]
 
@@ -8,7 +11,9 @@ extern crate alloc;
 
 use alloc::boxed::Box;
 use alloc::format;
-use alloc::string::ToString;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
 use bsp::entry;
 use bsp::hal::{
     clocks::{init_clocks_and_plls, Clock},
@@ -17,6 +22,8 @@ use bsp::hal::{
     uart::{DataBits, StopBits, UartConfig},
     watchdog::Watchdog,
 };
+use core::cell::{Cell, RefCell};
+use critical_section::Mutex;
 use embedded_alloc::Heap;
 use embedded_graphics::{
     mono_font::{ascii::FONT_5X8, MonoTextStyleBuilder},
@@ -24,16 +31,24 @@ use embedded_graphics::{
     prelude::*,
     text::Text,
 };
-use embedded_hal::digital::v2::PinState;
-use fugit::RateExtU32;
+use embedded_hal::digital::v2::{OutputPin, PinState};
+use embedded_hal::watchdog::{Watchdog as _, WatchdogDisable, WatchdogEnable};
+use fugit::{ExtU32, RateExtU32};
 use rp_pico as bsp;
 use ssd1306::{prelude::*, I2CDisplayInterface, Ssd1306};
 
 const HEAP_SIZE: usize = 200 * 1024;
+const DISPLAY_RETRY_TICKS: u32 = 50; // 5 secs between display init retries
+const WATCHDOG_MS: u32 = 2000; // a loop stuck this long resets the board
+const PANIC_HOLD_MS: u32 = 5000; // how long a panic stays on screen before the reset
+const MAX_DOOR_OPEN_MS: u32 = 60_000; // a stuck open button closes the doors after this
 static mut HEAP: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
 
-mod button;
-mod elevator;
+use pico_toy_elevator::{boot, button, command, elevator, sound, text};
+
+// a copy of the recent events, readable from the panic handler
+type EventLog = heapless::HistoryBuffer<elevator::ElevatorEvent, { elevator::EVENT_LOG_SIZE }>;
+static EVENT_LOG: Mutex<RefCell<EventLog>> = Mutex::new(RefCell::new(EventLog::new()));
 
 #[global_allocator]
 static ALLOCATOR: Heap = Heap::empty();
@@ -55,6 +70,8 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
     )
     .ok()
     .unwrap();
+    // leave the message up for a while, then reset so the boot counter sees the crash
+    watchdog.start((PANIC_HOLD_MS * 1000).micros());
 
     let pins = bsp::Pins::new(
         pac.IO_BANK0,
@@ -76,7 +93,12 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
     let interface = I2CDisplayInterface::new(i2c);
     let mut display = Ssd1306::new(interface, DisplaySize128x64, DisplayRotation::Rotate0)
         .into_buffered_graphics_mode();
-    display.init().unwrap();
+    if display.init().is_err() {
+        // nowhere to show the message, just wait for the reset
+        loop {
+            cortex_m::asm::wfi();
+        }
+    }
 
     let text_style = MonoTextStyleBuilder::new()
         .font(&FONT_5X8)
@@ -84,37 +106,46 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
         .build();
 
     display.clear(BinaryColor::Off).unwrap();
-    let mut s = format!("{:?}", info);
-    let mut x = 0;
-    let mut y = 0;
-    let width = 128 / 6 - 1;
-    while !s.is_empty() {
-        let end_of_line = s
-            .find(|c| {
-                if c == '\n' || x > width {
-                    x = 0;
-                    y += 1;
-                    true
-                } else {
-                    x += 1;
-                    false
-                }
-            })
-            .unwrap_or(s.len());
-        let (line, rest) = s.split_at(end_of_line);
-        let sz = text_style.font.character_size;
+    let sz = text_style.font.character_size;
+    let columns = 128 / sz.width as usize;
+    let rows = 64 / sz.height as usize;
+    let mut lines = Vec::new();
+    // where it panicked, without the directories, then why
+    if let Some(location) = info.location() {
+        let file = location.file().rsplit('/').next().unwrap_or_default();
+        lines.push(format!("{}:{}", file, location.line()));
+    }
+    let message = format!("{}", info.message());
+    lines.extend(
+        text::wrap_lines(&message, columns)
+            .take(2)
+            .map(String::from),
+    );
+    // then what led here, newest first, as many as fit
+    critical_section::with(|cs| {
+        let log = EVENT_LOG.borrow_ref(cs);
+        let (older, newer) = log.as_slices();
+        for event in newer.iter().rev().chain(older.iter().rev()) {
+            if lines.len() >= rows {
+                break;
+            }
+            lines.push(format!("{:?}", event));
+        }
+    });
+    for (y, line) in lines.iter().enumerate() {
         Text::new(
-            line,
-            Point::new(x * sz.width as i32, y * sz.height as i32),
+            text::truncate(line, columns),
+            Point::new(0, (y as i32 + 1) * sz.height as i32),
             text_style,
         )
         .draw(&mut display)
         .unwrap();
-        s = rest.strip_prefix('\n').unwrap_or(rest).to_string();
     }
     display.flush().unwrap();
 
-    loop {}
+    loop {
+        cortex_m::asm::wfi();
+    }
 }
 
 #[entry]
@@ -129,6 +160,9 @@ fn main() -> ! {
     let core = pac::CorePeripherals::take().unwrap();
     let mut watchdog = Watchdog::new(pac.WATCHDOG);
     let sio = Sio::new(pac.SIO);
+    let diagnostic = boot::record_boot();
+    // still counting down if a panic reset the board, started again below
+    watchdog.disable();
 
     // External high-speed crystal on the pico board is 12Mhz
     let external_xtal_freq_hz = 12_000_000u32;
@@ -154,6 +188,7 @@ fn main() -> ! {
     );
 
     // ボタンの管理
+    // a switch with no LED behind it goes in as `button::Unlit::new(button::PlainButton::new(..))`
     let mut buttons: heapless::Vec<Box<dyn button::LedButtonTrait>, 2> = heapless::Vec::new();
 
     // LedButton インスタンスを作成して Vec に追加するマクロ
@@ -183,10 +218,55 @@ fn main() -> ! {
     let interface = I2CDisplayInterface::new(i2c);
     let mut display = Ssd1306::new(interface, DisplaySize128x64, DisplayRotation::Rotate0)
         .into_buffered_graphics_mode();
-    display.init().unwrap();
+    // the elevator runs without a screen, init is retried from the loop
+    let mut display_ok = display.init().is_ok();
+    let mut display_retry = 0;
     // Empty the display:
-    display.clear(BinaryColor::Off).unwrap();
-    display.flush().unwrap();
+    if display_ok {
+        display.clear(BinaryColor::Off).unwrap();
+        display_ok = display.flush().is_ok();
+    }
+
+    // keeps rebooting, most likely a wiring fault; show the pinout until power cycled
+    if let boot::DiagnosticMode::RepeatedResets(boots) = diagnostic {
+        // the count is kept in flash, so clear it or the next power-up lands here too
+        boot::mark_stable();
+        if display_ok {
+            let text_style = MonoTextStyleBuilder::new()
+                .font(&FONT_5X8)
+                .text_color(BinaryColor::On)
+                .build();
+            let header = format!("RESET x{} CHECK WIRING", boots);
+            let lines = [
+                header.as_str(),
+                "OLED SDA GP16 SCL GP17",
+                "VOICE TX GP0 RX GP1",
+                "A 4/2 B 5/3 (LED/BTN)",
+                "B2 22/27 B1 26/28",
+                "1 19/21 2 18/20",
+                "3 10/8 4 11/9",
+                "5 13/14 6 12/15",
+            ];
+            for (y, line) in lines.iter().enumerate() {
+                let _ = Text::new(line, Point::new(0, (y as i32 + 1) * 8 - 1), text_style)
+                    .draw(&mut display);
+            }
+            let _ = display.flush();
+        }
+        // the on-board LED blinks the reset count too, for when it's the display that's miswired
+        let mut led = pins.led.into_push_pull_output();
+        loop {
+            for _ in 0..boots {
+                let _ = led.set_high();
+                delay.delay_ms(200);
+                let _ = led.set_low();
+                delay.delay_ms(300);
+            }
+            delay.delay_ms(1500);
+        }
+    }
+
+    watchdog.start((WATCHDOG_MS * 1000).micros());
 
     // ATP3012xx の初期化
     let uart_pins = (pins.gpio0.into_function(), pins.gpio1.into_function());
@@ -197,12 +277,33 @@ fn main() -> ! {
         )
         .unwrap();
 
+    // every floor LED below is wired active low; an active high one would use
+    // `led_button_new!(inverted ...)`, which starts low so it stays off, and a
+    // button that pulls high when pressed `LedButton::new_active_high`.
+    // ForcedButton lets the serial protocol press them too. A press has to
+    // be seen on two reads in a row, so a one-tick glitch doesn't call a floor,
+    // and holding one for 1.5 secs cancels its call
     macro_rules! led_button_new {
+        (@chain $led:expr, $button:expr, $led_polarity:expr) => {
+            Box::new(button::ForcedButton::new(
+                button::LedButton::with_debounce($led, $button.into_pull_up_input(), 2)
+                    .with_long_press(15)
+                    .with_polarity(button::Polarity::ActiveLow, $led_polarity),
+            ))
+        };
+        (inverted $led:expr, $button:expr) => {
+            led_button_new!(@chain
+                $led.into_push_pull_output_in_state(PinState::Low),
+                $button,
+                button::Polarity::ActiveHigh
+            )
+        };
         ($led:expr, $button:expr) => {
-            Box::new(button::LedButton::new(
+            led_button_new!(@chain
                 $led.into_push_pull_output_in_state(PinState::High),
-                $button.into_pull_up_input(),
-            ))
+                $button,
+                button::Polarity::ActiveLow
+            )
         };
     }
 
@@ -215,43 +316,118 @@ fn main() -> ! {
         (4, "4", led_button_new!(pins.gpio11, pins.gpio9)),
         (5, "5", led_button_new!(pins.gpio13, pins.gpio14)),
         (6, "6", led_button_new!(pins.gpio12, pins.gpio15)),
-    ]);
+    ])
+    // landing call at the lobby, left floating it just reads as released
+    .with_summon_button(1, button::PlainButton::new(pins.gpio6.into_pull_up_input()));
+
+    elevator.set_max_door_open_ms(MAX_DOOR_OPEN_MS);
+
+    // piezo buzzer on GP7 (PWM slice 3, channel B), its pitch follows the car's speed
+    let pwm_slices = bsp::hal::pwm::Slices::new(pac.PWM, &mut pac.RESETS);
+    let mut buzzer_pwm = pwm_slices.pwm3;
+    buzzer_pwm.channel_b.output_to(pins.gpio7);
+    elevator.set_sound(sound::PwmBuzzer::new(
+        buzzer_pwm,
+        clocks.system_clock.freq().to_Hz(),
+    ));
+    elevator.set_acceleration_ramp(true);
+    elevator.set_travel_tone(true);
+
+    elevator.on_event(|event| {
+        critical_section::with(|cs| EVENT_LOG.borrow_ref_mut(cs).write(event));
+    });
 
+    // the speech chip and the serial commands share the one UART, and no spare
+    // pins are left for UART1. TX is the speech chip's input, so commands get
+    // no reply; the ATP3012's own output on RX is skipped by the LineReader
+    let (uart_rx, uart_tx) = uart.split();
     elevator.on_announce(move |message: &[u8]| {
-        uart.write_full_blocking(message);
+        uart_tx.write_full_blocking(message);
     });
+    let mut command_line = command::LineReader::new();
+
+    // the display is owned by the loop, so hand the contrast over through a cell
+    let contrast = Rc::new(Cell::new(None));
+    let pending_contrast = contrast.clone();
+    elevator.on_brightness(move |value: u8| {
+        pending_contrast.set(Some(value));
+    });
+
+    // hold the A button while powering up to run the self-test
+    if buttons[0].is_pressed() == Ok(true) {
+        elevator.start_self_test();
+    }
+
+    // held while the open button is down
+    let mut open_hold = None;
+    let mut uptime_ticks = 0;
 
     delay.delay_ms(100);
     loop {
-        let mut i = 0;
-        display.clear(BinaryColor::Off).unwrap();
-        for button in buttons.iter_mut() {
-            if button.is_pressed().unwrap() {
-                match i {
-                    0 => {
-                        if elevator.set_door_open(false) {
-                            button.turn_on().unwrap();
-                        } else {
-                            button.turn_off().unwrap();
-                        }
-                    }
-                    1 => {
-                        if elevator.set_door_open(true) {
-                            button.turn_on().unwrap();
-                        } else {
-                            button.turn_off().unwrap();
-                        }
-                    }
-                    _ => {}
-                }
-            } else {
-                button.turn_off().unwrap();
+        // A closes, B opens; both held resolves to open. A failing pin reads
+        // as released and goes into the event log instead of stopping the car
+        let mut pressed = [false; 2];
+        for (button, pressed) in buttons.iter().zip(pressed.iter_mut()) {
+            match button.is_pressed() {
+                Ok(value) => *pressed = value,
+                Err(error) => elevator.report_pin_fault(error),
+            }
+        }
+        let [close, open] = pressed;
+        let accepted = elevator.door_command(open, close);
+        for (button, on) in buttons
+            .iter_mut()
+            .zip([close && !open && accepted, open && accepted])
+        {
+            if let Err(error) = button.set_on(on) {
+                elevator.report_pin_fault(error);
+            }
+        }
+        if open {
+            open_hold.get_or_insert_with(|| elevator.acquire_hold());
+        } else {
+            open_hold = None;
+        }
+        // serial commands; one that can't be carried out goes into the event log
+        let mut byte = [0];
+        while let Ok(1) = uart_rx.read_raw(&mut byte) {
+            let Some(command) = command_line.push(byte[0]) else {
+                continue;
+            };
+            if let Err(error) = command.and_then(|command| command.apply(&mut elevator)) {
+                elevator.report_command_error(error);
             }
-            i += 1;
         }
         elevator.advance();
-        elevator.draw(&mut display).unwrap();
-        display.flush().unwrap();
-        delay.delay_ms(100);
+        watchdog.feed();
+        if uptime_ticks < boot::STABLE_MS / elevator::TICK_MS {
+            uptime_ticks += 1;
+            if uptime_ticks == boot::STABLE_MS / elevator::TICK_MS {
+                boot::mark_stable();
+            }
+        }
+        let mut recovered = false;
+        if !display_ok {
+            display_retry += 1;
+            if display_retry >= DISPLAY_RETRY_TICKS {
+                display_retry = 0;
+                display_ok = display.init().is_ok();
+                recovered = display_ok;
+            }
+        }
+        if display_ok {
+            if let Some(value) = contrast.take() {
+                display_ok = display
+                    .set_brightness(Brightness::custom(0x2, value))
+                    .is_ok();
+            }
+        }
+        // only push a frame over I2C when something visible changed
+        if display_ok && (recovered || elevator.needs_redraw()) {
+            display.clear(BinaryColor::Off).unwrap();
+            elevator.draw(&mut display).unwrap();
+            display_ok = display.flush().is_ok();
+        }
+        delay.delay_ms(elevator::TICK_MS);
     }
 }
@@ -0,0 +1,60 @@
+use embedded_hal::PwmPin;
+use rp_pico::hal::pwm::{FreeRunning, Slice, SliceId};
+
+// the PWM counter runs at this rate, so `top` stays in range from 16 Hz up
+const COUNTER_HZ: u32 = 1_000_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SoundEffect {
+    // the tone while travelling, in Hz
+    Travel(u32),
+    Silence,
+}
+
+pub trait Sound {
+    fn play(&mut self, effect: SoundEffect);
+}
+
+// a piezo buzzer on either output of a PWM slice, driven with a square wave
+pub struct PwmBuzzer<I>
+where
+    I: SliceId,
+{
+    slice: Slice<I, FreeRunning>,
+    sys_hz: u32,
+}
+
+impl<I> PwmBuzzer<I>
+where
+    I: SliceId,
+{
+    // `slice` should already have the buzzer pin routed to one of its channels
+    pub fn new(mut slice: Slice<I, FreeRunning>, sys_hz: u32) -> Self {
+        slice.set_div_int((sys_hz / COUNTER_HZ).clamp(1, 255) as u8);
+        slice.set_div_frac(0);
+        slice.channel_a.set_duty(0);
+        slice.channel_b.set_duty(0);
+        slice.enable();
+        Self { slice, sys_hz }
+    }
+}
+
+impl<I> Sound for PwmBuzzer<I>
+where
+    I: SliceId,
+{
+    fn play(&mut self, effect: SoundEffect) {
+        // both channels get the same duty, so it doesn't matter which one the pin is on
+        let duty = match effect {
+            SoundEffect::Travel(hz) => {
+                let counter_hz = self.sys_hz / (self.sys_hz / COUNTER_HZ).clamp(1, 255);
+                let top = (counter_hz / hz.max(1)).clamp(2, u16::MAX as u32) - 1;
+                self.slice.set_top(top as u16);
+                top as u16 / 2
+            }
+            SoundEffect::Silence => 0,
+        };
+        self.slice.channel_a.set_duty(duty);
+        self.slice.channel_b.set_duty(duty);
+    }
+}
@@ -0,0 +1,104 @@
+// splits `s` into lines of at most `width` characters, breaking at embedded
+// newlines, then at the last space that fits, then mid-word
+pub fn wrap_lines(s: &str, width: usize) -> WrapLines<'_> {
+    WrapLines {
+        rest: Some(s),
+        width: width.max(1),
+    }
+}
+
+// the first `width` characters of `s`, for one line that must not wrap
+pub fn truncate(s: &str, width: usize) -> &str {
+    match s.char_indices().nth(width) {
+        Some((end, _)) => &s[..end],
+        None => s,
+    }
+}
+
+pub struct WrapLines<'a> {
+    rest: Option<&'a str>,
+    width: usize,
+}
+
+impl<'a> Iterator for WrapLines<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = self.rest?;
+        let (line, next) = match rest.find('\n') {
+            Some(end) => (&rest[..end], Some(&rest[end + 1..])),
+            None => (rest, None),
+        };
+        let Some((cut, _)) = line.char_indices().nth(self.width) else {
+            // fits as is
+            self.rest = next;
+            return Some(line);
+        };
+        let (end, resume) = if line[cut..].starts_with(' ') {
+            // the next word starts right after the boundary
+            (cut, cut)
+        } else {
+            match line[..cut].rfind(' ') {
+                Some(space) if !line[..space].trim_end_matches(' ').is_empty() => {
+                    (line[..space].trim_end_matches(' ').len(), space)
+                }
+                _ => (cut, cut),
+            }
+        };
+        // the spaces at a break belong to neither line
+        let resume = line.len() - line[resume..].trim_start_matches(' ').len();
+        self.rest = if resume == line.len() {
+            next
+        } else {
+            Some(&rest[resume..])
+        };
+        Some(&line[..end])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrap(s: &str, width: usize) -> Vec<&str> {
+        wrap_lines(s, width).collect()
+    }
+
+    #[test]
+    fn short_text_is_one_line() {
+        assert_eq!(wrap("lobby", 8), ["lobby"]);
+        assert_eq!(wrap("", 8), [""]);
+    }
+
+    #[test]
+    fn breaks_at_the_last_space_that_fits() {
+        assert_eq!(wrap("going up to 6", 8), ["going up", "to 6"]);
+        assert_eq!(wrap("a  b  c", 3), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn a_space_on_the_boundary_is_a_break() {
+        assert_eq!(wrap("hello world foo", 5), ["hello", "world", "foo"]);
+        assert_eq!(wrap("aaaa bbbb", 4), ["aaaa", "bbbb"]);
+        assert_eq!(wrap("aaaa   ", 4), ["aaaa"]);
+    }
+
+    #[test]
+    fn long_words_are_split() {
+        assert_eq!(wrap("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+        assert_eq!(wrap("ab cdefghij", 4), ["ab", "cdef", "ghij"]);
+    }
+
+    #[test]
+    fn truncate_keeps_the_first_characters() {
+        assert_eq!(truncate("DoorOpened(3)", 4), "Door");
+        assert_eq!(truncate("Idle", 8), "Idle");
+    }
+
+    #[test]
+    fn embedded_newlines_end_a_line() {
+        assert_eq!(wrap("ab\ncd efgh", 4), ["ab", "cd", "efgh"]);
+        assert_eq!(wrap("ab\n\ncd", 4), ["ab", "", "cd"]);
+        assert_eq!(wrap("abcdef\ngh", 4), ["abcd", "ef", "gh"]);
+    }
+}
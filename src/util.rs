@@ -0,0 +1,22 @@
+// xorshift32, small enough for the demo traffic and needs no hardware entropy
+pub struct XorShift32 {
+    state: u32,
+}
+
+impl XorShift32 {
+    pub fn new(seed: u32) -> Self {
+        // an all-zero state would only ever produce zeros
+        Self {
+            state: if seed == 0 { 0x2545_f491 } else { seed },
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+}